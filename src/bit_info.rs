@@ -99,7 +99,7 @@ impl BitInfo {
             let val = Info {
                 module: match i {
                     j if j < data_end => Info::DATA,
-                    j if j < ecc_end * 8 => Info::EC,
+                    j if j < ecc_end => Info::EC,
                     _ => Info::REMAINDER,
                 },
                 block,
@@ -146,3 +146,25 @@ impl BitInfo {
         bit_info
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_info_counts_modules() {
+        // 44 codewords and 7 remainder bits, 10 of the codewords are ec at ECL::Low
+        let bit_info = BitInfo::new(Mode::Byte, Version(2), ECL::Low, Mask::M0);
+        let count = |kind: Module| {
+            bit_info
+                .matrix
+                .value
+                .iter()
+                .filter(|info| info.module == kind)
+                .count()
+        };
+        assert_eq!(count(Info::DATA), 34 * 8);
+        assert_eq!(count(Info::EC), 10 * 8);
+        assert_eq!(count(Info::REMAINDER), 7);
+    }
+}
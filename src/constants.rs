@@ -15,6 +15,202 @@ pub const GEN_POLYNOMIALS: [[u8; 30]; 31] = gen_polynomials();
 pub const VERSION_INFO: [usize; 41] = version_info();
 pub const FORMAT_INFO: [[u32; 8]; 4] = format_info();
 
+/// Micro QR data capacity in bits by version (M1-M4) and ECL, 0 if the ECL isn't available.
+/// M1 and M3 end with a 4 bit data codeword.
+pub const MICRO_DATA_BITS: [[u16; 3]; 5] = [
+    [0, 0, 0],
+    [20, 0, 0],
+    [40, 32, 0],
+    [84, 68, 0],
+    [128, 112, 80],
+];
+/// Micro QR always has a single block
+pub const MICRO_EC_CODEWORDS: [[u16; 3]; 5] =
+    [[0, 0, 0], [2, 0, 0], [5, 6, 0], [6, 8, 0], [8, 10, 14]];
+/// Indexed by symbol number (version and ECL), then Micro mask number
+pub const MICRO_FORMAT_INFO: [[u32; 4]; 8] = micro_format_info();
+
+/// rMQR height and width by version, R7x43 = 1 ... R17x139 = 32
+pub const RMQR_SIZES: [[u8; 2]; 33] = [
+    [0, 0],
+    [7, 43],
+    [7, 59],
+    [7, 77],
+    [7, 99],
+    [7, 139],
+    [9, 43],
+    [9, 59],
+    [9, 77],
+    [9, 99],
+    [9, 139],
+    [11, 27],
+    [11, 43],
+    [11, 59],
+    [11, 77],
+    [11, 99],
+    [11, 139],
+    [13, 27],
+    [13, 43],
+    [13, 59],
+    [13, 77],
+    [13, 99],
+    [13, 139],
+    [15, 43],
+    [15, 59],
+    [15, 77],
+    [15, 99],
+    [15, 139],
+    [17, 43],
+    [17, 59],
+    [17, 77],
+    [17, 99],
+    [17, 139],
+];
+/// rMQR only has `ECL::Medium` and `ECL::High`, the other columns are 0
+pub const RMQR_DATA_CODEWORDS: [[u16; 4]; 33] = [
+    [0, 0, 0, 0],
+    [0, 6, 0, 3],
+    [0, 12, 0, 7],
+    [0, 20, 0, 10],
+    [0, 28, 0, 14],
+    [0, 44, 0, 24],
+    [0, 12, 0, 7],
+    [0, 21, 0, 11],
+    [0, 31, 0, 17],
+    [0, 42, 0, 22],
+    [0, 63, 0, 33],
+    [0, 7, 0, 5],
+    [0, 19, 0, 11],
+    [0, 31, 0, 15],
+    [0, 43, 0, 23],
+    [0, 57, 0, 29],
+    [0, 84, 0, 42],
+    [0, 12, 0, 7],
+    [0, 27, 0, 13],
+    [0, 38, 0, 20],
+    [0, 53, 0, 29],
+    [0, 73, 0, 35],
+    [0, 106, 0, 54],
+    [0, 33, 0, 15],
+    [0, 48, 0, 26],
+    [0, 67, 0, 31],
+    [0, 88, 0, 48],
+    [0, 127, 0, 69],
+    [0, 39, 0, 21],
+    [0, 56, 0, 28],
+    [0, 78, 0, 38],
+    [0, 100, 0, 56],
+    [0, 152, 0, 76],
+];
+pub const RMQR_EC_CODEWORDS: [[u16; 4]; 33] = [
+    [0, 0, 0, 0],
+    [0, 7, 0, 10],
+    [0, 9, 0, 14],
+    [0, 12, 0, 22],
+    [0, 16, 0, 30],
+    [0, 24, 0, 44],
+    [0, 9, 0, 14],
+    [0, 12, 0, 22],
+    [0, 18, 0, 32],
+    [0, 24, 0, 44],
+    [0, 36, 0, 66],
+    [0, 8, 0, 10],
+    [0, 12, 0, 20],
+    [0, 16, 0, 32],
+    [0, 24, 0, 44],
+    [0, 32, 0, 60],
+    [0, 48, 0, 90],
+    [0, 9, 0, 14],
+    [0, 14, 0, 28],
+    [0, 22, 0, 40],
+    [0, 32, 0, 56],
+    [0, 40, 0, 78],
+    [0, 60, 0, 112],
+    [0, 18, 0, 36],
+    [0, 26, 0, 48],
+    [0, 36, 0, 72],
+    [0, 48, 0, 88],
+    [0, 72, 0, 130],
+    [0, 22, 0, 40],
+    [0, 32, 0, 60],
+    [0, 44, 0, 84],
+    [0, 60, 0, 104],
+    [0, 80, 0, 156],
+];
+pub const RMQR_NUM_BLOCKS: [[u8; 4]; 33] = [
+    [0, 0, 0, 0],
+    [0, 1, 0, 1],
+    [0, 1, 0, 1],
+    [0, 1, 0, 1],
+    [0, 1, 0, 1],
+    [0, 1, 0, 2],
+    [0, 1, 0, 1],
+    [0, 1, 0, 1],
+    [0, 1, 0, 2],
+    [0, 1, 0, 2],
+    [0, 2, 0, 3],
+    [0, 1, 0, 1],
+    [0, 1, 0, 1],
+    [0, 1, 0, 2],
+    [0, 1, 0, 2],
+    [0, 2, 0, 2],
+    [0, 2, 0, 3],
+    [0, 1, 0, 1],
+    [0, 1, 0, 1],
+    [0, 1, 0, 2],
+    [0, 2, 0, 2],
+    [0, 2, 0, 3],
+    [0, 3, 0, 4],
+    [0, 1, 0, 2],
+    [0, 1, 0, 2],
+    [0, 2, 0, 3],
+    [0, 2, 0, 4],
+    [0, 3, 0, 5],
+    [0, 1, 0, 2],
+    [0, 2, 0, 2],
+    [0, 2, 0, 3],
+    [0, 3, 0, 4],
+    [0, 4, 0, 6],
+];
+/// Char count indicator length by version and mode
+pub const RMQR_CCI_BITS: [[u8; 4]; 33] = [
+    [0, 0, 0, 0],
+    [4, 3, 3, 2],
+    [5, 5, 4, 3],
+    [6, 5, 5, 4],
+    [7, 6, 5, 5],
+    [7, 6, 6, 5],
+    [5, 5, 4, 3],
+    [6, 5, 5, 4],
+    [7, 6, 5, 5],
+    [7, 6, 6, 5],
+    [8, 7, 6, 6],
+    [4, 4, 3, 2],
+    [6, 5, 5, 4],
+    [7, 6, 5, 5],
+    [7, 6, 6, 5],
+    [8, 7, 6, 6],
+    [8, 7, 7, 6],
+    [5, 5, 4, 3],
+    [6, 6, 5, 5],
+    [7, 6, 6, 5],
+    [7, 7, 6, 6],
+    [8, 7, 7, 6],
+    [8, 8, 7, 7],
+    [7, 6, 6, 5],
+    [7, 7, 6, 5],
+    [8, 7, 7, 6],
+    [8, 7, 7, 6],
+    [9, 8, 7, 7],
+    [7, 6, 6, 5],
+    [8, 7, 6, 6],
+    [8, 7, 7, 6],
+    [8, 8, 7, 6],
+    [9, 8, 8, 7],
+];
+/// Indexed by `ECL::High as 1 << 5 | version - 1`, before masking
+pub const RMQR_FORMAT_INFO: [u32; 64] = rmqr_format_info();
+
 const fn num_data_modules() -> [u16; 41] {
     let mut table = [0; 41];
 
@@ -225,6 +421,53 @@ const fn format_info() -> [[u32; 8]; 4] {
     array
 }
 
+const fn micro_format_info() -> [[u32; 4]; 8] {
+    let mut array = [[0; 4]; 8];
+
+    let mut symbol: u32 = 0;
+    while symbol < 8 {
+        let mut mask = 0;
+        while mask < 4 {
+            let format = ((symbol << 2) | mask) << 10;
+            let mut dividend = format;
+
+            while dividend >= 0b100_0000_0000 {
+                let mut divisor = 0b101_0011_0111;
+                divisor <<= (32 - dividend.leading_zeros()) - 11;
+
+                dividend ^= divisor;
+            }
+
+            array[symbol as usize][mask as usize] = (format | dividend) ^ 0b100_0100_0100_0101;
+            mask += 1;
+        }
+        symbol += 1;
+    }
+
+    array
+}
+
+/// Same BCH code as `version_info`, 6 data bits
+const fn rmqr_format_info() -> [u32; 64] {
+    let mut array = [0; 64];
+
+    let mut data: u32 = 0;
+    while data < 64 {
+        let shifted_data = data << 12;
+        let mut dividend = shifted_data;
+
+        while dividend >= 0b1_0000_0000_0000 {
+            let mut divisor = 0b1_1111_0010_0101;
+            divisor <<= (32 - dividend.leading_zeros()) - 13;
+
+            dividend ^= divisor;
+        }
+        array[data as usize] = shifted_data | dividend;
+        data += 1;
+    }
+    array
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,4 +486,33 @@ mod tests {
         assert_eq!(FORMAT_INFO[ECL::High as usize][Mask::M0 as usize], 0x1689);
         assert_eq!(FORMAT_INFO[ECL::High as usize][Mask::M7 as usize], 0x083B);
     }
+
+    #[test]
+    fn micro_format_information_works() {
+        assert_eq!(MICRO_FORMAT_INFO[0][0], 0x4445);
+        assert_eq!(MICRO_FORMAT_INFO[1][2], 0x5FC0);
+        assert_eq!(MICRO_FORMAT_INFO[7][3], 0x3BBA);
+    }
+
+    #[test]
+    fn rmqr_tables_are_consistent() {
+        for version in 1..=32 {
+            for ecl in [ECL::Medium, ECL::High] {
+                let ecl = ecl as usize;
+                let total = RMQR_DATA_CODEWORDS[version][ecl] + RMQR_EC_CODEWORDS[version][ecl];
+                assert_eq!(
+                    total,
+                    RMQR_DATA_CODEWORDS[version][1] + RMQR_EC_CODEWORDS[version][1]
+                );
+                assert_eq!(
+                    RMQR_EC_CODEWORDS[version][ecl] % RMQR_NUM_BLOCKS[version][ecl] as u16,
+                    0
+                );
+            }
+        }
+        // same BCH code as version info
+        for version in 7..=40 {
+            assert_eq!(RMQR_FORMAT_INFO[version] as usize, VERSION_INFO[version]);
+        }
+    }
 }
@@ -1,7 +1,10 @@
 use crate::{
-    constants::{NUM_DATA_MODULES, NUM_EC_CODEWORDS},
-    encoding::{encode_alphanumeric, encode_byte, encode_numeric, num_cci_bits},
-    qr_code::{Mode, Version, ECL},
+    constants::{
+        MICRO_DATA_BITS, NUM_DATA_MODULES, NUM_EC_CODEWORDS, RMQR_CCI_BITS, RMQR_DATA_CODEWORDS,
+        RMQR_SIZES,
+    },
+    encoding::{segment, segment_with, Header, Segment, ECI_UTF8, MODES},
+    qr_code::{Mode, Symbol, Version, ECL},
 };
 
 #[derive(Debug)]
@@ -10,6 +13,7 @@ pub struct Data {
     pub mode: Mode,
     pub version: Version,
     pub ecl: ECL,
+    pub symbol: Symbol,
 }
 
 impl Data {
@@ -25,59 +29,114 @@ impl Data {
         min_ecl: ECL,
         strict_ecl: bool,
     ) -> Option<Self> {
-        let mut bits = 0;
-        bits += 4 + num_cci_bits(min_version, mode);
-        let char_len = text.len();
-        match mode {
-            Mode::Numeric => {
-                bits += (char_len / 3) * 10;
-                match char_len % 3 {
-                    2 => bits += 7,
-                    1 => bits += 4,
-                    _ => (),
-                }
-            }
-            Mode::Alphanumeric => {
-                bits += (char_len / 2) * 11 + (char_len % 2) * 6;
+        Self::new_eci(
+            text,
+            mode,
+            None,
+            min_version,
+            strict_version,
+            min_ecl,
+            strict_ecl,
+        )
+    }
+
+    /// Same as `new_verbose`, but starts with an ECI designator telling scanners how to read bytes.
+    /// None if `eci` is above 999999, the last assignment number.
+    pub fn new_eci(
+        text: &str,
+        mode: Mode,
+        eci: Option<u32>,
+        min_version: Version,
+        strict_version: bool,
+        min_ecl: ECL,
+        strict_ecl: bool,
+    ) -> Option<Self> {
+        Self::from_segments(
+            &[Segment::new(mode, text)],
+            Header {
+                eci,
+                ..Header::default()
+            },
+            min_version,
+            strict_version,
+            min_ecl,
+            strict_ecl,
+        )
+    }
+
+    /// Splits `text` into Numeric, Alphanumeric, Byte and Kanji segments with the fewest total bits.
+    /// `mode` is set to the highest mode used.
+    /// Without `header.eci`, `ECI_UTF8` is written if a Byte segment holds non-ASCII text.
+    pub fn new_segmented(
+        text: &str,
+        header: Header,
+        min_version: Version,
+        strict_version: bool,
+        min_ecl: ECL,
+        strict_ecl: bool,
+    ) -> Option<Self> {
+        // best segments only change when char count indicators grow at 10 and 27
+        let mut data = None;
+        for (first, last) in [(1, 9), (10, 26), (27, 40)] {
+            if min_version.0 > last {
+                continue;
             }
-            Mode::Byte => {
-                bits += char_len * 8;
+            let version = Version(min_version.0.max(first));
+            let segments = segment(text, version, header.fnc1.is_some());
+            // raw UTF-8 would be read as the ISO-8859-1 default otherwise
+            let utf8 = segments
+                .iter()
+                .any(|s| s.mode == Mode::Byte && !s.text.is_ascii());
+            let header = Header {
+                eci: header.eci.or(utf8.then_some(ECI_UTF8)),
+                ..header
+            };
+            data = Self::from_segments(
+                &segments,
+                header,
+                version,
+                strict_version,
+                min_ecl,
+                strict_ecl,
+            );
+            match data {
+                Some(ref x) if x.version.0 > last => continue,
+                _ => break,
             }
         }
-        let mut data_codewords = (NUM_DATA_MODULES[min_version.0] / 8) as usize;
+        data
+    }
+
+    /// None if the segments don't fit, or `header.eci` is above 999999
+    pub fn from_segments(
+        segments: &[Segment],
+        header: Header,
+        min_version: Version,
+        strict_version: bool,
+        min_ecl: ECL,
+        strict_ecl: bool,
+    ) -> Option<Self> {
+        if header.eci.is_some_and(|eci| eci > 999999) {
+            return None;
+        }
+        let num_bits = |version: usize| {
+            let bits: usize = segments.iter().map(|s| s.num_bits(Version(version))).sum();
+            bits + header.num_bits()
+        };
+        let data_codewords = |version: usize, ecl: usize| {
+            (NUM_DATA_MODULES[version] / 8 - NUM_EC_CODEWORDS[version][ecl]) as usize
+        };
 
         let mut min_version = min_version.0;
-        let mut req_codewords = (bits + 7) / 8;
+        let mut req_codewords = num_bits(min_version).div_ceil(8);
 
-        while req_codewords
-            > (data_codewords - NUM_EC_CODEWORDS[min_version][min_ecl as usize] as usize)
-            && min_version < 40
-        {
-            if strict_version {
+        while req_codewords > data_codewords(min_version, min_ecl as usize) {
+            if strict_version || min_version == 40 {
                 return None;
             }
-
             min_version += 1;
-
-            data_codewords = (NUM_DATA_MODULES[min_version] / 8) as usize;
             // char count indicator length increase
-            match mode {
-                Mode::Byte => {
-                    if min_version == 10 {
-                        bits += 8;
-                    }
-                }
-                _ => {
-                    if min_version == 10 || min_version == 27 {
-                        bits += 2;
-                    }
-                }
-            }
-            req_codewords = (bits + 7) / 8;
-        }
-
-        if min_version > 40 {
-            return None;
+            req_codewords = num_bits(min_version).div_ceil(8);
         }
 
         let mut max_ecl = min_ecl;
@@ -85,28 +144,185 @@ impl Data {
         if !strict_ecl {
             let ecls = [ECL::Low, ECL::Medium, ECL::Quartile, ECL::High];
             for new_ecl in (min_ecl as usize + 1..ecls.len()).rev() {
-                if req_codewords <= data_codewords - NUM_EC_CODEWORDS[min_version][new_ecl] as usize
-                {
+                if req_codewords <= data_codewords(min_version, new_ecl) {
                     max_ecl = ecls[new_ecl];
                     break;
                 }
             }
         }
 
+        let mode = segments
+            .iter()
+            .map(|s| s.mode)
+            .max_by_key(|&mode| mode as u8)
+            .unwrap_or(Mode::Numeric);
         let mut data = Data {
-            bits: BitVec::with_capacity(data_codewords * 8),
+            bits: BitVec::with_capacity(NUM_DATA_MODULES[min_version] as usize),
             mode,
             version: Version(min_version),
             ecl: max_ecl,
+            symbol: Symbol::Normal,
         };
 
-        match mode {
-            Mode::Numeric => encode_numeric(&mut data, text),
-            Mode::Alphanumeric => encode_alphanumeric(&mut data, text),
-            Mode::Byte => encode_byte(&mut data, text),
+        header.encode(&mut data);
+        for segment in segments {
+            segment.encode(&mut data);
         }
         Some(data)
     }
+
+    /// Micro QR, `Version(1..=4)` is M1-M4 and only `ECL::Low`, `ECL::Medium` and `ECL::Quartile` exist.
+    /// `mode` None splits `text` into segments with the fewest bits, like `new_segmented`.
+    pub fn new_micro(
+        text: &str,
+        mode: Option<Mode>,
+        min_version: Version,
+        strict_version: bool,
+        min_ecl: ECL,
+        strict_ecl: bool,
+    ) -> Option<Self> {
+        // M1 only has Numeric, M2 adds Alphanumeric, M3 adds Byte and Kanji
+        let num_cci_bits = |version: usize, mode: Mode| match mode {
+            Mode::Numeric => Some(version + 2),
+            Mode::Alphanumeric if version >= 2 => Some(version + 1),
+            Mode::Byte if version >= 3 => Some(version + 1),
+            Mode::Kanji if version >= 3 => Some(version),
+            _ => None,
+        };
+        let ecls = [ECL::Low, ECL::Medium, ECL::Quartile];
+        let capacity = |version: usize, ecl: usize| match ecl < ecls.len() {
+            true => MICRO_DATA_BITS[version][ecl] as usize,
+            false => 0,
+        };
+
+        for version in min_version.0.max(1)..=4 {
+            let head_bits = MODES.map(|m| match mode {
+                Some(mode) if mode != m => None,
+                // mode indicator is 0-3 bits
+                _ => num_cci_bits(version, m).map(|cci| version - 1 + cci),
+            });
+            let Some(segments) = segment_with(text, head_bits, false) else {
+                if strict_version {
+                    return None;
+                }
+                continue;
+            };
+            let num_bits: usize = segments
+                .iter()
+                .map(|s| head_bits[s.mode as usize].unwrap() + s.num_char_bits())
+                .sum();
+
+            // capacity is 0 where the ECL doesn't exist, e.g. Medium on M1
+            let fits =
+                |ecl: usize| capacity(version, ecl) > 0 && num_bits <= capacity(version, ecl);
+            if !fits(min_ecl as usize) {
+                if strict_version {
+                    return None;
+                }
+                continue;
+            }
+
+            let mut ecl = min_ecl;
+            if !strict_ecl {
+                for new_ecl in (min_ecl as usize + 1..ecls.len()).rev() {
+                    if fits(new_ecl) {
+                        ecl = ecls[new_ecl];
+                        break;
+                    }
+                }
+            }
+
+            let mut bits = BitVec::with_capacity(capacity(version, ecl as usize));
+            for segment in &segments {
+                bits.push_n(segment.mode as usize, version - 1);
+                bits.push_n(
+                    segment.char_count(),
+                    num_cci_bits(version, segment.mode).unwrap(),
+                );
+                segment.encode_chars(&mut bits);
+            }
+            let mode = segments
+                .iter()
+                .map(|s| s.mode)
+                .max_by_key(|&mode| mode as u8)
+                .unwrap_or(Mode::Numeric);
+            return Some(Data {
+                bits,
+                mode,
+                version: Version(version),
+                ecl,
+                symbol: Symbol::Micro,
+            });
+        }
+        None
+    }
+
+    /// rMQR, `Version(1..=32)` is R7x43 ... R17x139, see `RMQR_SIZES`.
+    /// Unless `strict_version`, the symbol only gets wider, it keeps the height of `min_version`.
+    /// Only `ECL::Medium` and `ECL::High` exist, `ECL::Low` and `ECL::Quartile` round up.
+    pub fn new_rmqr(
+        text: &str,
+        mode: Option<Mode>,
+        min_version: Version,
+        strict_version: bool,
+        min_ecl: ECL,
+        strict_ecl: bool,
+    ) -> Option<Self> {
+        if !(1..=32).contains(&min_version.0) {
+            return None;
+        }
+        let min_ecl = match min_ecl {
+            ECL::Low | ECL::Medium => ECL::Medium,
+            ECL::Quartile | ECL::High => ECL::High,
+        };
+        let height = RMQR_SIZES[min_version.0][0];
+
+        for version in min_version.0..=32 {
+            if RMQR_SIZES[version][0] != height || (strict_version && version != min_version.0) {
+                break;
+            }
+            // mode indicator is 3 bits
+            let head_bits = MODES.map(|m| match mode {
+                Some(mode) if mode != m => None,
+                _ => Some(3 + RMQR_CCI_BITS[version][m as usize] as usize),
+            });
+            let segments = segment_with(text, head_bits, false)?;
+            let num_bits: usize = segments
+                .iter()
+                .map(|s| head_bits[s.mode as usize].unwrap() + s.num_char_bits())
+                .sum();
+            let capacity = |ecl: ECL| RMQR_DATA_CODEWORDS[version][ecl as usize] as usize * 8;
+
+            if num_bits > capacity(min_ecl) {
+                continue;
+            }
+            let ecl = match !strict_ecl && num_bits <= capacity(ECL::High) {
+                true => ECL::High,
+                false => min_ecl,
+            };
+
+            let mut bits = BitVec::with_capacity(capacity(ecl));
+            for segment in &segments {
+                let cci_bits = RMQR_CCI_BITS[version][segment.mode as usize] as usize;
+                bits.push_n(segment.mode as usize + 1, 3);
+                bits.push_n(segment.char_count(), cci_bits);
+                segment.encode_chars(&mut bits);
+            }
+            let mode = segments
+                .iter()
+                .map(|s| s.mode)
+                .max_by_key(|&mode| mode as u8)
+                .unwrap_or(Mode::Numeric);
+            return Some(Data {
+                bits,
+                mode,
+                version: Version(version),
+                ecl,
+                symbol: Symbol::Rmqr,
+            });
+        }
+        None
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -150,6 +366,10 @@ impl BitVec {
         self.len
     }
     pub fn push_n(&mut self, input: usize, n: usize) {
+        // Micro QR M1 has no mode indicator
+        if n == 0 {
+            return;
+        }
         let gap = (8 - (self.len % 8)) % 8;
         self.len += n;
 
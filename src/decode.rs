@@ -0,0 +1,644 @@
+#[cfg(feature = "kanji")]
+use crate::encoding::kanji_char;
+use crate::{
+    constants::{FORMAT_INFO, NUM_BLOCKS, NUM_DATA_MODULES, NUM_EC_CODEWORDS, VERSION_INFO},
+    encoding::{num_cci_bits, StructuredAppend, GS},
+    error_correction::{correct, deinterleave},
+    matrix::{Matrix, Module},
+    qr_code::{mask_fn, Fnc1, Mask, Mode, Version, ECL},
+    DecodeError,
+};
+
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    pub version: Version,
+    pub ecl: ECL,
+    pub mask: Mask,
+    /// Number of codewords fixed by error correction
+    pub corrected: usize,
+    /// Last ECI assignment number read, only UTF-8 and ISO-8859-1 change how bytes are read
+    pub eci: Option<u32>,
+    /// Position in a sequence from `generate_structured`, join the texts with `reassemble`
+    pub structured_append: Option<StructuredAppend>,
+    /// Alphanumeric '%' is already read as GS (0x1D), and "%%" as '%'
+    pub fnc1: Option<Fnc1>,
+}
+
+pub fn decode(matrix: &Matrix<Module>) -> Result<Decoded, DecodeError> {
+    decode_with(matrix.width, |x, y| matrix.get(x, y).has(Module::ON))
+}
+
+/// `grid` is row major, true is a dark module. Quiet zone must be excluded
+pub fn decode_bools(grid: &[bool], width: usize) -> Result<Decoded, DecodeError> {
+    if grid.len() != width * width {
+        return Err(DecodeError::InvalidWidth);
+    }
+    decode_with(width, |x, y| grid[y * width + x])
+}
+
+fn decode_with(width: usize, is_on: impl Fn(usize, usize) -> bool) -> Result<Decoded, DecodeError> {
+    if !(21..=177).contains(&width) || width % 4 != 1 {
+        return Err(DecodeError::InvalidWidth);
+    }
+    let version = Version((width - 17) / 4);
+
+    // function patterns are placed exactly like the encoder, leaving data modules unset
+    let mut template = Matrix::new(version, Module(0));
+    template.set_finder();
+    template.set_alignment();
+    template.set_timing();
+
+    if version.0 >= 7 && read_version(&template, &is_on) != Some(version) {
+        return Err(DecodeError::InvalidVersion);
+    }
+    let (ecl, mask) = read_format(&template, &is_on).ok_or(DecodeError::InvalidFormat)?;
+
+    template.set_format(ecl, mask);
+    template.set_version();
+
+    let codewords = NUM_DATA_MODULES[version.0] as usize / 8;
+    let mask_bit = mask_fn(mask);
+
+    let mut sequence = vec![0; codewords];
+    for (i, (x, y)) in template
+        .data_coords()
+        .into_iter()
+        .take(codewords * 8)
+        .enumerate()
+    {
+        if is_on(x, y) ^ mask_bit(x as u16, y as u16) {
+            sequence[i / 8] |= 1 << (7 - (i % 8));
+        }
+    }
+
+    let num_ec_codewords = NUM_EC_CODEWORDS[version.0][ecl as usize] as usize;
+    let blocks = NUM_BLOCKS[version.0][ecl as usize] as usize;
+    let ecc_per_block = num_ec_codewords / blocks;
+
+    let mut data = Vec::with_capacity(codewords - num_ec_codewords);
+    let mut corrected = 0;
+    for mut block in deinterleave(&sequence, version, ecl) {
+        corrected += correct(&mut block, ecc_per_block, &[])?;
+        data.extend_from_slice(&block[..block.len() - ecc_per_block]);
+    }
+
+    let payload = parse_segments(&data, version)?;
+    Ok(Decoded {
+        text: payload.text,
+        version,
+        ecl,
+        mask,
+        corrected,
+        eci: payload.eci,
+        structured_append: payload.structured_append,
+        fnc1: payload.fnc1,
+    })
+}
+
+/// Joins the texts of a whole Structured Append sequence, in any order.
+/// The parity is checked against the UTF-8 bytes of the result, like `generate_structured` computes it.
+pub fn reassemble(symbols: &[Decoded]) -> Result<String, DecodeError> {
+    let first = symbols
+        .first()
+        .and_then(|decoded| decoded.structured_append)
+        .ok_or(DecodeError::InvalidSequence)?;
+
+    let mut texts = vec![None; first.total as usize];
+    for decoded in symbols {
+        match decoded.structured_append {
+            Some(header)
+                if header.total == first.total
+                    && header.parity == first.parity
+                    && header.index < header.total =>
+            {
+                // a repeated index would overwrite a symbol
+                if texts[header.index as usize]
+                    .replace(decoded.text.as_str())
+                    .is_some()
+                {
+                    return Err(DecodeError::InvalidSequence);
+                }
+            }
+            _ => return Err(DecodeError::InvalidSequence),
+        }
+    }
+
+    let text: String = texts
+        .into_iter()
+        .collect::<Option<_>>()
+        .ok_or(DecodeError::InvalidSequence)?;
+    if StructuredAppend::parity(&text) != first.parity {
+        return Err(DecodeError::InvalidSequence);
+    }
+    Ok(text)
+}
+
+fn read_format(
+    template: &Matrix<Module>,
+    is_on: impl Fn(usize, usize) -> bool,
+) -> Option<(ECL, Mask)> {
+    let mut format = 0;
+    let mut format_copy = 0;
+    for i in 0..15 {
+        let [(x, y), (x_copy, y_copy)] = template.format_coords(i);
+        format |= (is_on(x, y) as u32) << i;
+        format_copy |= (is_on(x_copy, y_copy) as u32) << i;
+    }
+
+    let ecls = [ECL::Low, ECL::Medium, ECL::Quartile, ECL::High];
+    let masks = [
+        Mask::M0,
+        Mask::M1,
+        Mask::M2,
+        Mask::M3,
+        Mask::M4,
+        Mask::M5,
+        Mask::M6,
+        Mask::M7,
+    ];
+
+    // BCH(15, 5) has a minimum distance of 7, so up to 3 flipped bits are recoverable
+    let mut best = None;
+    let mut best_distance = 4;
+    for ecl in ecls {
+        for mask in masks {
+            let expected = FORMAT_INFO[ecl as usize][mask as usize];
+            let distance =
+                ((expected ^ format).count_ones()).min((expected ^ format_copy).count_ones());
+            if distance < best_distance {
+                best_distance = distance;
+                best = Some((ecl, mask));
+            }
+        }
+    }
+    best
+}
+
+fn read_version(
+    template: &Matrix<Module>,
+    is_on: impl Fn(usize, usize) -> bool,
+) -> Option<Version> {
+    let mut info = 0;
+    let mut info_copy = 0;
+    for i in 0..18 {
+        let [(x, y), (x_copy, y_copy)] = template.version_coords(i);
+        info |= (is_on(x, y) as usize) << i;
+        info_copy |= (is_on(x_copy, y_copy) as usize) << i;
+    }
+
+    // BCH(18, 6) has a minimum distance of 8, so up to 3 flipped bits are recoverable
+    let mut best = None;
+    let mut best_distance = 4;
+    for (version, expected) in VERSION_INFO.iter().enumerate().skip(7) {
+        let distance = ((expected ^ info).count_ones()).min((expected ^ info_copy).count_ones());
+        if distance < best_distance {
+            best_distance = distance;
+            best = Some(Version(version));
+        }
+    }
+    best
+}
+
+struct Payload {
+    text: String,
+    eci: Option<u32>,
+    structured_append: Option<StructuredAppend>,
+    fnc1: Option<Fnc1>,
+}
+
+fn parse_segments(data: &[u8], version: Version) -> Result<Payload, DecodeError> {
+    let mut reader = BitReader { data, pos: 0 };
+    let mut payload = Payload {
+        text: String::new(),
+        eci: None,
+        structured_append: None,
+        fnc1: None,
+    };
+    // bytes since the last ECI designator
+    let mut bytes = Vec::new();
+
+    // a terminator may be cut short or omitted if the data capacity is full
+    while reader.remaining() >= 4 {
+        match reader.read(4)? {
+            0b0000 => break,
+            0b0101 => payload.fnc1 = Some(Fnc1::First),
+            0b1001 => payload.fnc1 = Some(Fnc1::Second(reader.read(8)? as u8)),
+            0b0011 => {
+                payload.structured_append = Some(StructuredAppend {
+                    index: reader.read(4)? as u8,
+                    total: reader.read(4)? as u8 + 1,
+                    parity: reader.read(8)? as u8,
+                });
+            }
+            0b0001 => {
+                let len = reader.read(num_cci_bits(version, Mode::Numeric))?;
+                for _ in 0..len / 3 {
+                    push_digits(&mut bytes, reader.read(10)?, 3)?;
+                }
+                match len % 3 {
+                    2 => push_digits(&mut bytes, reader.read(7)?, 2)?,
+                    1 => push_digits(&mut bytes, reader.read(4)?, 1)?,
+                    _ => (),
+                }
+            }
+            0b0010 => {
+                let len = reader.read(num_cci_bits(version, Mode::Alphanumeric))?;
+                let mut chars = Vec::with_capacity(len);
+                for _ in 0..len / 2 {
+                    let group = reader.read(11)?;
+                    chars.push(b45_to_byte(group / 45)?);
+                    chars.push(b45_to_byte(group % 45)?);
+                }
+                if len % 2 == 1 {
+                    chars.push(b45_to_byte(reader.read(6)?)?);
+                }
+
+                if payload.fnc1.is_none() {
+                    bytes.append(&mut chars);
+                    continue;
+                }
+                // "%%" is '%', a lone '%' is GS
+                let mut chars = chars.into_iter().peekable();
+                while let Some(b) = chars.next() {
+                    if b != b'%' {
+                        bytes.push(b);
+                    } else if chars.next_if_eq(&b'%').is_some() {
+                        bytes.push(b'%');
+                    } else {
+                        bytes.push(GS as u8);
+                    }
+                }
+            }
+            0b0100 => {
+                let len = reader.read(num_cci_bits(version, Mode::Byte))?;
+                for _ in 0..len {
+                    bytes.push(reader.read(8)? as u8);
+                }
+            }
+            #[cfg(feature = "kanji")]
+            0b1000 => {
+                let len = reader.read(num_cci_bits(version, Mode::Kanji))?;
+                for _ in 0..len {
+                    let value = reader.read(13)?;
+                    let sjis = ((value / 0xC0) << 8) | (value % 0xC0);
+                    let sjis = if sjis + 0x8140 <= 0x9FFC {
+                        sjis + 0x8140
+                    } else {
+                        sjis + 0xC140
+                    };
+                    let c = kanji_char(sjis as u16).ok_or(DecodeError::InvalidData)?;
+                    push_bytes(&mut payload, &mut bytes);
+                    payload.text.push(c);
+                }
+            }
+            0b0111 => {
+                let eci = match reader.read(8)? {
+                    b if b >> 7 == 0 => b,
+                    b if b >> 6 == 0b10 => (b & 0b11_1111) << 8 | reader.read(8)?,
+                    b if b >> 5 == 0b110 => (b & 0b1_1111) << 16 | reader.read(16)?,
+                    _ => return Err(DecodeError::InvalidData),
+                };
+                push_bytes(&mut payload, &mut bytes);
+                payload.eci = Some(eci as u32);
+            }
+            _ => return Err(DecodeError::InvalidMode),
+        }
+    }
+
+    push_bytes(&mut payload, &mut bytes);
+    Ok(payload)
+}
+
+/// Moves bytes to text, interpreted according to the current ECI
+fn push_bytes(payload: &mut Payload, bytes: &mut Vec<u8>) {
+    let latin_1 = |bytes: Vec<u8>| bytes.into_iter().map(char::from).collect::<String>();
+    let bytes = std::mem::take(bytes);
+
+    match payload.eci {
+        Some(1 | 3) => payload.text.push_str(&latin_1(bytes)),
+        // UTF-8 is what nearly every encoder (including this one) writes,
+        // fall back to the ISO-8859-1 default otherwise
+        _ => match String::from_utf8(bytes) {
+            Ok(text) => payload.text.push_str(&text),
+            Err(err) => payload.text.push_str(&latin_1(err.into_bytes())),
+        },
+    }
+}
+
+fn push_digits(bytes: &mut Vec<u8>, group: usize, digits: u32) -> Result<(), DecodeError> {
+    if group >= 10usize.pow(digits) {
+        return Err(DecodeError::InvalidData);
+    }
+    for i in (0..digits).rev() {
+        bytes.push(b'0' + ((group / 10usize.pow(i)) % 10) as u8);
+    }
+    Ok(())
+}
+
+fn b45_to_byte(value: usize) -> Result<u8, DecodeError> {
+    const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+    ALPHANUMERIC
+        .get(value)
+        .copied()
+        .ok_or(DecodeError::InvalidData)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+    fn read(&mut self, n: usize) -> Result<usize, DecodeError> {
+        if n > self.remaining() {
+            return Err(DecodeError::InvalidData);
+        }
+        let mut value = 0;
+        for _ in 0..n {
+            let bit = (self.data[self.pos / 8] >> (7 - (self.pos % 8))) & 1;
+            value = (value << 1) | bit as usize;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate, generate_qart, generate_structured, generate_structured_per_symbol,
+        qart::WeightPixel, QrOptions,
+    };
+
+    fn round_trip(input: &str, qr_options: &QrOptions) -> Decoded {
+        let qr_code = generate(input, qr_options).unwrap();
+        let decoded = decode(&qr_code.matrix).unwrap();
+        assert_eq!(decoded.text, input);
+        assert_eq!(decoded.version, qr_code.version);
+        assert_eq!(decoded.ecl, qr_code.ecl);
+        assert_eq!(decoded.mask, qr_code.mask);
+        decoded
+    }
+
+    #[test]
+    fn decode_round_trips() {
+        round_trip("https://github.com/subygan/qrust", &QrOptions::new());
+        round_trip("0123456789012345", &QrOptions::new());
+        round_trip("HELLO WORLD $%*+-./:", &QrOptions::new());
+        round_trip("", &QrOptions::new());
+        round_trip("ORDER 12345678901234 ref=abc", &QrOptions::new());
+        round_trip("0042 ünïcödé HTTPS://EXAMPLE.COM", &QrOptions::new());
+    }
+
+    #[test]
+    fn decode_fnc1() {
+        let qr_options = QrOptions::new().fnc1(Some(Fnc1::Second(37)));
+        let decoded = round_trip("AB%\x1dCD%%12345 ab%", &qr_options);
+        assert_eq!(decoded.fnc1, Some(Fnc1::Second(37)));
+        let qr_options = QrOptions::new().fnc1(Some(Fnc1::Second(b'a' + 100)));
+        let decoded = round_trip("ab", &qr_options);
+        assert_eq!(decoded.fnc1, Some(Fnc1::Second(197)));
+        for indicator in [100, 150, 164, 191, 255] {
+            let qr_options = QrOptions::new().fnc1(Some(Fnc1::Second(indicator)));
+            assert_eq!(
+                generate("ab", &qr_options).unwrap_err(),
+                crate::QrError::InvalidEncoding
+            );
+        }
+
+        let qr_options = QrOptions::new()
+            .fnc1(Some(Fnc1::First))
+            .mode(Some(Mode::Alphanumeric));
+        let decoded = round_trip("10AB%\x1d21CD", &qr_options);
+        assert_eq!(decoded.fnc1, Some(Fnc1::First));
+    }
+
+    #[test]
+    fn decode_eci() {
+        let decoded = round_trip("ünïcödé ✓", &QrOptions::new());
+        assert_eq!(decoded.eci, Some(26));
+
+        let decoded = round_trip("ascii", &QrOptions::new());
+        assert_eq!(decoded.eci, None);
+
+        for eci in [0, 127, 128, 16383, 16384, 999999] {
+            let decoded = round_trip("ECI", &QrOptions::new().eci(Some(eci)));
+            assert_eq!(decoded.eci, Some(eci));
+        }
+
+        // bytes 0xC3 0xA9, which is "Ã©" in ISO-8859-1
+        let decoded = decode(
+            &generate("é", &QrOptions::new().eci(Some(3)))
+                .unwrap()
+                .matrix,
+        )
+        .unwrap();
+        assert_eq!(decoded.text, "Ã©");
+    }
+
+    #[cfg(feature = "kanji")]
+    #[test]
+    fn decode_kanji() {
+        let qr_code = generate("漢字モード", &QrOptions::new()).unwrap();
+        assert_eq!(qr_code.mode, Mode::Kanji);
+        assert_eq!(decode(&qr_code.matrix).unwrap().text, "漢字モード");
+
+        let decoded = round_trip("漢字モード 0123456789 ünïcödé", &QrOptions::new());
+        assert_eq!(decoded.eci, Some(26));
+
+        // Kanji chars that end up in a Byte segment are still UTF-8
+        for input in ["a点a", "ab点cd", "x点"] {
+            assert_eq!(round_trip(input, &QrOptions::new()).eci, Some(26));
+        }
+        assert_eq!(round_trip("漢字モード", &QrOptions::new()).eci, None);
+    }
+
+    #[test]
+    fn decode_round_trips_all_versions() {
+        let ecls = [ECL::Low, ECL::Medium, ECL::Quartile, ECL::High];
+        let masks = [
+            Mask::M0,
+            Mask::M1,
+            Mask::M2,
+            Mask::M3,
+            Mask::M4,
+            Mask::M5,
+            Mask::M6,
+            Mask::M7,
+        ];
+        for version in 1..=40 {
+            let ecl = ecls[version % 4];
+            let mask = masks[version % 8];
+            let input = "QRUST".repeat(version);
+            round_trip(
+                &input,
+                &QrOptions::new()
+                    .min_version(Version(version))
+                    .strict_version(true)
+                    .min_ecl(ecl)
+                    .strict_ecl(true)
+                    .mask(Some(mask)),
+            );
+        }
+    }
+
+    #[test]
+    fn decode_qart() {
+        let version = Version::new(7);
+        let width = version.0 * 4 + 17;
+        let pixel_weights: Vec<_> = (0..width * width)
+            .map(|i| WeightPixel::new((i / width + i % 3) % 2 == 0, 127))
+            .collect();
+
+        let input = "https://github.com/subygan/qrust";
+        let qr_code = generate_qart(
+            input,
+            &QrOptions::new().min_version(version),
+            &pixel_weights,
+        )
+        .unwrap();
+        assert_eq!(decode(&qr_code.matrix).unwrap().text, input);
+    }
+
+    #[test]
+    fn decode_reference_encoder() {
+        for (input, version, ec_level) in [
+            ("https://example.com/", 3, qrcode::EcLevel::H),
+            ("HELLO 0123456789 hello", 5, qrcode::EcLevel::M),
+            ("01234567890123456789", 10, qrcode::EcLevel::Q),
+            ("reference", 27, qrcode::EcLevel::L),
+        ] {
+            let code = qrcode::QrCode::with_version(
+                input.as_bytes(),
+                qrcode::Version::Normal(version),
+                ec_level,
+            )
+            .unwrap();
+            let grid: Vec<bool> = code
+                .to_colors()
+                .into_iter()
+                .map(|c| c == qrcode::Color::Dark)
+                .collect();
+
+            let decoded = decode_bools(&grid, code.width()).unwrap();
+            assert_eq!(decoded.text, input);
+            assert_eq!(decoded.version, Version(version as usize));
+        }
+    }
+
+    #[test]
+    fn decode_rejects_invalid_width() {
+        assert_eq!(
+            decode_bools(&vec![false; 20 * 20], 20).unwrap_err(),
+            DecodeError::InvalidWidth
+        );
+    }
+
+    #[test]
+    fn decode_corrects_damage() {
+        let input = "https://github.com/subygan/qrust";
+        let mut qr_code = generate(
+            input,
+            &QrOptions::new()
+                .min_version(Version(3))
+                .strict_version(true)
+                .min_ecl(ECL::Medium)
+                .strict_ecl(true),
+        )
+        .unwrap();
+        let width = qr_code.matrix.width;
+
+        // 2 modules of the first codeword, in the bottom right corner
+        for (x, y) in [(width - 1, width - 1), (width - 2, width - 2)] {
+            *qr_code.matrix.get_mut(x, y) ^= Module::ON;
+        }
+        let decoded = decode(&qr_code.matrix).unwrap();
+        assert_eq!(decoded.text, input);
+        assert_eq!(decoded.corrected, 1);
+
+        // destroy 3 column pairs, far past 6 codewords per block
+        for y in 9..width {
+            for x in width - 8..width - 2 {
+                *qr_code.matrix.get_mut(x, y) ^= Module::ON;
+            }
+        }
+        assert_eq!(
+            decode(&qr_code.matrix).unwrap_err(),
+            DecodeError::Uncorrectable
+        );
+    }
+
+    #[test]
+    fn decode_structured_append() {
+        let input = "Structured Append 0123456789 ünïcödé ".repeat(8);
+        let qr_options = QrOptions::new()
+            .min_version(Version(5))
+            .strict_version(true)
+            .min_ecl(ECL::Medium)
+            .strict_ecl(true);
+        let qr_codes = generate_structured(&input, &qr_options).unwrap();
+        assert!(qr_codes.len() > 1);
+
+        let mut symbols = Vec::new();
+        for qr_code in qr_codes.iter().rev() {
+            assert_eq!(qr_code.version, Version(5));
+            assert_eq!(qr_code.ecl, ECL::Medium);
+            symbols.push(decode(&qr_code.matrix).unwrap());
+        }
+        assert_eq!(reassemble(&symbols).unwrap(), input);
+
+        // as many symbols as the total, but index 1 twice instead of index 0
+        symbols.pop();
+        symbols.push(decode(&qr_codes[1].matrix).unwrap());
+        assert_eq!(reassemble(&symbols), Err(DecodeError::InvalidSequence));
+        symbols.pop();
+        assert_eq!(reassemble(&symbols), Err(DecodeError::InvalidSequence));
+
+        let single = generate_structured("short", &QrOptions::new()).unwrap();
+        assert_eq!(single.len(), 1);
+        let decoded = decode(&single[0].matrix).unwrap();
+        assert_eq!(decoded.structured_append.unwrap().total, 1);
+        assert_eq!(reassemble(&[decoded]).unwrap(), "short");
+
+        let symbol_options = [
+            QrOptions::new()
+                .min_version(Version(3))
+                .strict_version(true)
+                .min_ecl(ECL::High)
+                .strict_ecl(true),
+            QrOptions::new()
+                .min_version(Version(4))
+                .strict_version(true)
+                .min_ecl(ECL::Low)
+                .strict_ecl(true),
+        ];
+        let qr_codes =
+            generate_structured_per_symbol(&input, &qr_options, &symbol_options).unwrap();
+        let symbols: Vec<_> = qr_codes
+            .iter()
+            .map(|qr_code| (qr_code.version, qr_code.ecl))
+            .collect();
+        assert_eq!(symbols[0], (Version(3), ECL::High));
+        assert_eq!(symbols[1], (Version(4), ECL::Low));
+        assert!(symbols[2..]
+            .iter()
+            .all(|&symbol| symbol == (Version(5), ECL::Medium)));
+        let symbols: Vec<_> = qr_codes
+            .iter()
+            .map(|qr_code| decode(&qr_code.matrix).unwrap())
+            .collect();
+        assert_eq!(reassemble(&symbols).unwrap(), input);
+
+        let too_long = "A".repeat(16 * 25);
+        let qr_options = QrOptions::new().strict_version(true).strict_ecl(true);
+        assert_eq!(
+            generate_structured(&too_long, &qr_options).unwrap_err(),
+            crate::QrError::ExceedsMaxCapacity
+        );
+    }
+}
@@ -1,8 +1,244 @@
+use std::borrow::Cow;
+
 use crate::{
-    data::Data,
-    qr_code::{Mode, Version},
+    data::{BitVec, Data},
+    qr_code::{Fnc1, Mode, Version},
 };
 
+/// In the order used by `segment_with`, and by Micro QR mode indicators
+pub const MODES: [Mode; 4] = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte, Mode::Kanji];
+
+/// ECI assignment number for UTF-8
+pub const ECI_UTF8: u32 = 26;
+
+/// Group separator, ends variable length fields in FNC1 data
+pub const GS: char = '\x1d';
+
+/// Everything written before the segments, in this order
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Header {
+    pub structured_append: Option<StructuredAppend>,
+    pub eci: Option<u32>,
+    pub fnc1: Option<Fnc1>,
+}
+
+impl Header {
+    pub fn num_bits(&self) -> usize {
+        let mut bits = 0;
+        if self.structured_append.is_some() {
+            bits += StructuredAppend::NUM_BITS;
+        }
+        if let Some(eci) = self.eci {
+            bits += num_eci_bits(eci);
+        }
+        bits += match self.fnc1 {
+            Some(Fnc1::First) => 4,
+            Some(Fnc1::Second(_)) => 12,
+            None => 0,
+        };
+        bits
+    }
+
+    pub fn encode(&self, data: &mut Data) {
+        if let Some(structured_append) = self.structured_append {
+            encode_structured_append(data, structured_append);
+        }
+        if let Some(eci) = self.eci {
+            encode_eci(data, eci);
+        }
+        if let Some(fnc1) = self.fnc1 {
+            encode_fnc1(data, fnc1);
+        }
+    }
+}
+
+/// Run of characters encoded in a single mode
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Segment<'a> {
+    pub mode: Mode,
+    /// Exactly what's encoded, see `Segment::new_fnc1`
+    pub text: Cow<'a, str>,
+}
+
+impl<'a> Segment<'a> {
+    pub fn new(mode: Mode, text: &'a str) -> Self {
+        Segment {
+            mode,
+            text: Cow::Borrowed(text),
+        }
+    }
+
+    /// In Alphanumeric mode, escapes '%' as "%%" and writes GS as '%'
+    pub fn new_fnc1(mode: Mode, text: &'a str) -> Self {
+        if mode != Mode::Alphanumeric {
+            return Segment::new(mode, text);
+        }
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '%' => escaped.push_str("%%"),
+                GS => escaped.push('%'),
+                c => escaped.push(c),
+            }
+        }
+        Segment {
+            mode,
+            text: Cow::Owned(escaped),
+        }
+    }
+
+    /// mode indicator + char count indicator + data
+    pub fn num_bits(&self, version: Version) -> usize {
+        4 + num_cci_bits(version, self.mode) + self.num_char_bits()
+    }
+
+    /// Value of the char count indicator
+    pub fn char_count(&self) -> usize {
+        match self.mode {
+            Mode::Kanji => self.text.chars().count(),
+            _ => self.text.len(),
+        }
+    }
+
+    /// Data bits, without the mode and char count indicators
+    pub fn num_char_bits(&self) -> usize {
+        let char_len = self.char_count();
+        match self.mode {
+            Mode::Numeric => {
+                (char_len / 3) * 10
+                    + match char_len % 3 {
+                        2 => 7,
+                        1 => 4,
+                        _ => 0,
+                    }
+            }
+            Mode::Alphanumeric => (char_len / 2) * 11 + (char_len % 2) * 6,
+            Mode::Byte => char_len * 8,
+            Mode::Kanji => char_len * 13,
+        }
+    }
+
+    /// Writes the data bits, without the mode and char count indicators
+    pub fn encode_chars(&self, bits: &mut BitVec) {
+        match self.mode {
+            Mode::Numeric => push_numeric(bits, &self.text),
+            Mode::Alphanumeric => push_alphanumeric(bits, &self.text),
+            Mode::Byte => push_byte(bits, &self.text),
+            Mode::Kanji => push_kanji(bits, &self.text),
+        }
+    }
+
+    pub fn encode(&self, data: &mut Data) {
+        match self.mode {
+            Mode::Numeric => encode_numeric(data, &self.text),
+            Mode::Alphanumeric => encode_alphanumeric(data, &self.text),
+            Mode::Byte => encode_byte(data, &self.text),
+            Mode::Kanji => encode_kanji(data, &self.text),
+        }
+    }
+}
+
+/// Splits `input` into segments with the fewest total bits for `version`.
+/// Only the char count indicator lengths depend on `version`, so 1-9, 10-26 and 27-40 each share a result.
+/// With `fnc1`, Alphanumeric segments are escaped like `Segment::new_fnc1`.
+pub fn segment(input: &str, version: Version, fnc1: bool) -> Vec<Segment<'_>> {
+    let head_bits = MODES.map(|mode| Some(4 + num_cci_bits(version, mode)));
+    segment_with(input, head_bits, fnc1).expect("Byte mode can encode anything")
+}
+
+/// Same as `segment`, with the mode + char count indicator length of each mode in `MODES` order.
+/// None if a mode can't be used, and None is returned if some char can't be encoded.
+pub fn segment_with(
+    input: &str,
+    head_bits: [Option<usize>; 4],
+    fnc1: bool,
+) -> Option<Vec<Segment<'_>>> {
+    // Costs are in sixths of a bit, so every mode's per char cost is whole.
+    // Partial groups are handled by rounding up to a whole bit whenever a segment ends,
+    // e.g. 1 digit is 20/6 -> 4 bits, 2 digits are 40/6 -> 7 bits.
+    let char_cost = |c: char| -> [Option<usize>; 4] {
+        let alphanumeric = if fnc1 && c == '%' { 66 } else { 33 };
+        let costs = [20, alphanumeric, c.len_utf8() * 48, 78];
+        [0, 1, 2, 3].map(|m| is_encodable(c, MODES[m], fnc1).then_some(costs[m]))
+    };
+    let head_costs = head_bits.map(|bits| bits.map(|bits| bits * 6));
+    let round_up = |cost: usize| cost.div_ceil(6) * 6;
+
+    // costs[m] is the cheapest encoding of the chars so far that ends in mode m
+    let mut costs = head_costs;
+    // char_modes[i][m] is the mode of char i on the cheapest path to ending in mode m after char i
+    let mut char_modes: Vec<[Option<usize>; 4]> = Vec::new();
+
+    for c in input.chars() {
+        let mut modes = [None; 4];
+        let mut new_costs = [None; 4];
+        for (m, cost) in char_cost(c).into_iter().enumerate() {
+            if let (Some(cost), Some(prev)) = (cost, costs[m]) {
+                new_costs[m] = Some(prev + cost);
+                modes[m] = Some(m);
+            }
+        }
+
+        // switch modes after this char
+        let encoded = new_costs;
+        for (to, head_cost) in head_costs.into_iter().enumerate() {
+            let Some(head_cost) = head_cost else {
+                continue;
+            };
+            for (from, cost) in encoded.into_iter().enumerate() {
+                let Some(cost) = cost else {
+                    continue;
+                };
+                let cost = round_up(cost) + head_cost;
+                if new_costs[to].is_none_or(|x| cost < x) {
+                    new_costs[to] = Some(cost);
+                    modes[to] = Some(from);
+                }
+            }
+        }
+
+        costs = new_costs;
+        char_modes.push(modes);
+    }
+
+    let mut mode = (0..MODES.len())
+        .filter(|&m| costs[m].is_some())
+        .min_by_key(|&m| round_up(costs[m].unwrap()))?;
+
+    let mut modes = vec![0; char_modes.len()];
+    for (i, char_mode) in char_modes.iter().enumerate().rev() {
+        mode = char_mode[mode].expect("cheapest path is always encodable");
+        modes[i] = mode;
+    }
+
+    let new_segment = |mode, text| match fnc1 {
+        true => Segment::new_fnc1(mode, text),
+        false => Segment::new(mode, text),
+    };
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (j, (i, _)) in input.char_indices().enumerate().skip(1) {
+        if modes[j] != modes[j - 1] {
+            segments.push(new_segment(MODES[modes[j - 1]], &input[start..i]));
+            start = i;
+        }
+    }
+    if let Some(&mode) = modes.last() {
+        segments.push(new_segment(MODES[mode], &input[start..]));
+    }
+    Some(segments)
+}
+
+/// Whether `c` can be written in `mode`. With FNC1, GS is written as '%' in Alphanumeric mode.
+pub fn is_encodable(c: char, mode: Mode, fnc1: bool) -> bool {
+    match mode {
+        Mode::Numeric => c.is_ascii_digit(),
+        Mode::Alphanumeric => (c.is_ascii() && byte_to_b45(c as u8) < 45) || (fnc1 && c == GS),
+        Mode::Byte => true,
+        Mode::Kanji => kanji_value(c).is_some(),
+    }
+}
+
 pub fn encoding_mode(input: &str) -> Mode {
     let mut mode = Mode::Numeric;
     for b in input.bytes() {
@@ -16,6 +252,10 @@ pub fn encoding_mode(input: &str) -> Mode {
             break;
         }
     }
+    // 13 bits per character beats 2-4 bytes of UTF-8
+    if mode == Mode::Byte && input.chars().all(|c| kanji_value(c).is_some()) {
+        mode = Mode::Kanji;
+    }
     mode
 }
 
@@ -24,23 +264,26 @@ pub fn encode_numeric(data: &mut Data, input: &str) {
     data.bits.push_n(0b0001, 4);
     data.bits
         .push_n(input.len(), num_cci_bits(data.version, Mode::Numeric));
+    push_numeric(&mut data.bits, input);
+}
 
+pub fn push_numeric(bits: &mut BitVec, input: &str) {
     let input = input.as_bytes();
     for i in 0..(input.len() / 3) {
         let group = (input[i * 3] - b'0') as usize * 100
             + (input[i * 3 + 1] - b'0') as usize * 10
             + (input[i * 3 + 2] - b'0') as usize;
-        data.bits.push_n(group, 10);
+        bits.push_n(group, 10);
     }
 
     match input.len() % 3 {
         1 => {
             let group = input[input.len() - 1] - b'0';
-            data.bits.push_n(group.into(), 4);
+            bits.push_n(group.into(), 4);
         }
         2 => {
             let group = (input[input.len() - 2] - b'0') * 10 + (input[input.len() - 1] - b'0');
-            data.bits.push_n(group.into(), 7);
+            bits.push_n(group.into(), 7);
         }
         _ => (),
     }
@@ -52,19 +295,20 @@ pub fn encode_alphanumeric(qrdata: &mut Data, input: &str) {
         input.len(),
         num_cci_bits(qrdata.version, Mode::Alphanumeric),
     );
+    push_alphanumeric(&mut qrdata.bits, input);
+}
 
+pub fn push_alphanumeric(bits: &mut BitVec, input: &str) {
     let input = input.as_bytes();
 
     for i in 0..(input.len() / 2) {
         let group =
             byte_to_b45(input[i * 2]) as usize * 45 + byte_to_b45(input[i * 2 + 1]) as usize;
-        qrdata.bits.push_n(group, 11);
+        bits.push_n(group, 11);
     }
 
     if (input.len() & 1) == 1 {
-        qrdata
-            .bits
-            .push_n(byte_to_b45(input[input.len() - 1]).into(), 6);
+        bits.push_n(byte_to_b45(input[input.len() - 1]).into(), 6);
     }
 }
 
@@ -73,8 +317,130 @@ pub fn encode_byte(qrdata: &mut Data, input: &str) {
     qrdata
         .bits
         .push_n(input.len(), num_cci_bits(qrdata.version, Mode::Byte));
+    push_byte(&mut qrdata.bits, input);
+}
+
+pub fn push_byte(bits: &mut BitVec, input: &str) {
     for c in input.as_bytes() {
-        qrdata.bits.push_n((*c).into(), 8);
+        bits.push_n((*c).into(), 8);
+    }
+}
+
+/// `eci` is an assignment number from 0 to 999999
+pub fn encode_eci(qrdata: &mut Data, eci: u32) {
+    qrdata.bits.push_n(0b0111, 4);
+    match eci {
+        0..=127 => qrdata.bits.push_n(eci as usize, 8),
+        128..=16383 => qrdata.bits.push_n(0b10 << 14 | eci as usize, 16),
+        _ => {
+            assert!(eci <= 999999, "ECI assignment numbers end at 999999");
+            qrdata.bits.push_n(0b110 << 21 | eci as usize, 24)
+        }
+    }
+}
+
+/// Position of a symbol in a Structured Append sequence of up to 16 symbols
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StructuredAppend {
+    /// 0 based
+    pub index: u8,
+    pub total: u8,
+    /// Same for every symbol in the sequence
+    pub parity: u8,
+}
+
+impl StructuredAppend {
+    /// mode indicator + index + total + parity
+    pub const NUM_BITS: usize = 20;
+
+    /// XOR of every byte of the whole (unsplit) input, as UTF-8
+    pub fn parity(input: &str) -> u8 {
+        input.bytes().fold(0, |acc, b| acc ^ b)
+    }
+}
+
+pub fn encode_structured_append(qrdata: &mut Data, header: StructuredAppend) {
+    assert!(header.index < header.total && header.total <= 16);
+    qrdata.bits.push_n(0b0011, 4);
+    qrdata.bits.push_n(header.index.into(), 4);
+    qrdata.bits.push_n((header.total - 1).into(), 4);
+    qrdata.bits.push_n(header.parity.into(), 8);
+}
+
+pub fn encode_fnc1(qrdata: &mut Data, fnc1: Fnc1) {
+    match fnc1 {
+        Fnc1::First => qrdata.bits.push_n(0b0101, 4),
+        Fnc1::Second(indicator) => {
+            qrdata.bits.push_n(0b1001, 4);
+            qrdata.bits.push_n(indicator.into(), 8);
+        }
+    }
+}
+
+/// mode indicator + designator
+pub fn num_eci_bits(eci: u32) -> usize {
+    4 + match eci {
+        0..=127 => 8,
+        128..=16383 => 16,
+        _ => 24,
+    }
+}
+
+pub fn encode_kanji(qrdata: &mut Data, input: &str) {
+    qrdata.bits.push_n(0b1000, 4);
+    qrdata.bits.push_n(
+        input.chars().count(),
+        num_cci_bits(qrdata.version, Mode::Kanji),
+    );
+    push_kanji(&mut qrdata.bits, input);
+}
+
+pub fn push_kanji(bits: &mut BitVec, input: &str) {
+    for c in input.chars() {
+        let value = kanji_value(c).expect("input must be checked with encoding_mode");
+        bits.push_n(value.into(), 13);
+    }
+}
+
+/// 13 bit Kanji mode value of `c`,
+/// None if `c` isn't a double byte Shift JIS character in the 0x8140-0x9FFC or 0xE040-0xEBBF ranges
+#[cfg(feature = "kanji")]
+pub fn kanji_value(c: char) -> Option<u16> {
+    let mut buf = [0; 4];
+    let (sjis, _, unmappable) = encoding_rs::SHIFT_JIS.encode(c.encode_utf8(&mut buf));
+    if unmappable || sjis.len() != 2 {
+        return None;
+    }
+    let sjis = u16::from_be_bytes([sjis[0], sjis[1]]);
+
+    // a few characters don't survive the trip back, a scanner would read something else
+    if kanji_char(sjis) != Some(c) {
+        return None;
+    }
+
+    let offset = match sjis {
+        0x8140..=0x9FFC => 0x8140,
+        0xE040..=0xEBBF => 0xC140,
+        _ => return None,
+    };
+    let sjis = sjis - offset;
+    Some((sjis >> 8) * 0xC0 + (sjis & 0xFF))
+}
+
+#[cfg(not(feature = "kanji"))]
+pub fn kanji_value(_: char) -> Option<u16> {
+    None
+}
+
+/// Shift JIS character to char, None if it isn't a single character
+#[cfg(feature = "kanji")]
+pub fn kanji_char(sjis: u16) -> Option<char> {
+    let bytes = sjis.to_be_bytes();
+    let (text, had_errors) = encoding_rs::SHIFT_JIS.decode_without_bom_handling(&bytes);
+    let mut chars = text.chars();
+    match (had_errors, chars.next(), chars.next()) {
+        (false, Some(c), None) => Some(c),
+        _ => None,
     }
 }
 
@@ -87,6 +453,7 @@ pub fn num_cci_bits(version: Version, mode: Mode) -> usize {
     let mut base = match mode {
         Mode::Numeric => 10,
         Mode::Alphanumeric => 9,
+        Mode::Kanji => 8,
         _ => unreachable!("Unknown mode"),
     };
     if version.0 > 9 {
@@ -137,14 +504,14 @@ mod tests {
                 _ => continue,
             }
             if i == 8 {
-                v.push_n(num, 1);
+                v.push_n(num, 8);
                 num = 0;
                 i = 0;
             }
         }
 
         if i > 0 {
-            v.push_n(num, 1);
+            v.push_n(num >> (8 - i), i);
         }
 
         v
@@ -187,4 +554,212 @@ mod tests {
 
         assert_eq!(data.bits, get_data_bits("0100 00000001 00110000"));
     }
+
+    #[test]
+    fn encode_eci_works() {
+        let data = Data::new_eci(
+            "é",
+            Mode::Byte,
+            Some(ECI_UTF8),
+            Version(1),
+            false,
+            ECL::Low,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            data.bits,
+            get_data_bits("0111 00011010 0100 00000010 11000011 10101001")
+        );
+
+        let data = Data::new_eci(
+            "1",
+            Mode::Numeric,
+            Some(999999),
+            Version(1),
+            false,
+            ECL::Low,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            data.bits,
+            get_data_bits("0111 110 01111 01000010 00111111 0001 0000000001 0001")
+        );
+
+        let data = Data::new_eci(
+            "1",
+            Mode::Numeric,
+            Some(1000000),
+            Version(1),
+            false,
+            ECL::Low,
+            false,
+        );
+        assert!(data.is_none());
+    }
+
+    #[test]
+    fn encode_structured_append_works() {
+        let header = StructuredAppend {
+            index: 2,
+            total: 4,
+            parity: StructuredAppend::parity("ABC"),
+        };
+        let header = Header {
+            structured_append: Some(header),
+            ..Header::default()
+        };
+        let data = Data::from_segments(
+            &[Segment::new(Mode::Numeric, "1")],
+            header,
+            Version(1),
+            false,
+            ECL::Low,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            data.bits,
+            get_data_bits("0011 0010 0011 01000000 0001 0000000001 0001")
+        );
+    }
+
+    #[test]
+    fn encode_fnc1_works() {
+        let escaped = Segment::new_fnc1(Mode::Alphanumeric, "%\x1d");
+        assert_eq!(escaped.text, "%%%");
+        let header = Header {
+            fnc1: Some(Fnc1::First),
+            ..Header::default()
+        };
+        let data =
+            Data::from_segments(&[escaped], header, Version(1), false, ECL::Low, false).unwrap();
+        assert_eq!(
+            data.bits,
+            get_data_bits("0101 0010 000000011 11011010100 100110")
+        );
+
+        let header = Header {
+            fnc1: Some(Fnc1::Second(37)),
+            ..Header::default()
+        };
+        let data = Data::from_segments(&[], header, Version(1), false, ECL::Low, false).unwrap();
+        assert_eq!(data.bits, get_data_bits("1001 00100101"));
+
+        assert_eq!(
+            segment("AB%\x1d", Version(1), true),
+            [Segment::new(Mode::Alphanumeric, "AB%%%")]
+        );
+        assert_eq!(
+            segment("ab%\x1d", Version(1), false),
+            [Segment::new(Mode::Byte, "ab%\x1d")]
+        );
+    }
+
+    #[cfg(feature = "kanji")]
+    #[test]
+    fn encode_kanji_works() {
+        assert_eq!(encoding_mode("点茗"), Mode::Kanji);
+        assert_eq!(encoding_mode("点茗1"), Mode::Byte);
+
+        let data = Data::new("点茗", Mode::Kanji, Version(1), ECL::Low).unwrap();
+        assert_eq!(
+            data.bits,
+            get_data_bits("1000 00000010 0110110011111 1101010101010")
+        );
+    }
+
+    #[test]
+    fn segment_works() {
+        let input = "ORDER 12345678901234 ref=abc";
+        let segments = segment(input, Version(1), false);
+        assert_eq!(
+            segments,
+            [
+                Segment::new(Mode::Alphanumeric, "ORDER "),
+                Segment::new(Mode::Numeric, "12345678901234"),
+                Segment::new(Mode::Byte, " ref=abc"),
+            ]
+        );
+        let bits: usize = segments.iter().map(|s| s.num_bits(Version(1))).sum();
+        assert!(bits < Segment::new(Mode::Byte, input).num_bits(Version(1)));
+
+        assert_eq!(segment("", Version(1), false), []);
+        assert_eq!(
+            segment("0123", Version(1), false),
+            [Segment::new(Mode::Numeric, "0123")]
+        );
+        assert_eq!(
+            segment("ab", Version(40), false),
+            [Segment::new(Mode::Byte, "ab")]
+        );
+
+        let input = "SKU 000123456789012345678901234567890 https://example.com/p?id=42";
+        let segmented =
+            Data::new_segmented(input, Header::default(), Version(1), false, ECL::Low, true)
+                .unwrap();
+        let byte = Data::new(input, Mode::Byte, Version(1), ECL::Low).unwrap();
+        assert_eq!(segmented.mode, Mode::Byte);
+        assert!(segmented.version.0 < byte.version.0);
+    }
+
+    #[test]
+    fn segment_is_optimal() {
+        let alphabet = ['0', '1', 'A', ' ', 'a', 'é'];
+        let modes = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte];
+        let encodable = |mode: Mode, c: char| match mode {
+            Mode::Numeric => c.is_ascii_digit(),
+            Mode::Alphanumeric => c.is_ascii() && byte_to_b45(c as u8) < 45,
+            _ => true,
+        };
+
+        // random inputs up to 5 chars, against every mode assignment
+        let mut seed = 1usize;
+        for _ in 0..300 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let len = 1 + (seed >> 33) % 5;
+            let input: String = (0..len)
+                .map(|i| alphabet[(seed >> (40 + i * 4)) % alphabet.len()])
+                .collect();
+
+            for version in [Version(1), Version(10), Version(27)] {
+                let chars: Vec<(usize, char)> = input.char_indices().collect();
+                let mut best = usize::MAX;
+                for assignment in 0..modes.len().pow(len as u32) {
+                    let char_modes: Vec<Mode> = (0..len)
+                        .map(|i| modes[assignment / modes.len().pow(i as u32) % modes.len()])
+                        .collect();
+                    if chars
+                        .iter()
+                        .zip(&char_modes)
+                        .any(|(&(_, c), &m)| !encodable(m, c))
+                    {
+                        continue;
+                    }
+                    let mut bits = 0;
+                    let mut start = 0;
+                    for i in 1..=len {
+                        if i == len || char_modes[i] != char_modes[i - 1] {
+                            let end = chars.get(i).map_or(input.len(), |&(j, _)| j);
+                            bits += Segment::new(char_modes[i - 1], &input[start..end])
+                                .num_bits(version);
+                            start = end;
+                        }
+                    }
+                    best = best.min(bits);
+                }
+
+                let segments = segment(&input, version, false);
+                let bits: usize = segments.iter().map(|s| s.num_bits(version)).sum();
+                assert_eq!(bits, best, "{input:?} {version:?}");
+                assert_eq!(
+                    segments.iter().map(|s| s.text.as_ref()).collect::<String>(),
+                    input
+                );
+            }
+        }
+    }
 }
@@ -1,7 +1,12 @@
 use crate::{
-    constants::{GEN_POLYNOMIALS, NUM_BLOCKS, NUM_DATA_MODULES, NUM_EC_CODEWORDS},
-    data::Data,
-    math::{ANTILOG_TABLE, LOG_TABLE},
+    constants::{
+        GEN_POLYNOMIALS, MICRO_DATA_BITS, MICRO_EC_CODEWORDS, NUM_BLOCKS, NUM_DATA_MODULES,
+        NUM_EC_CODEWORDS, RMQR_DATA_CODEWORDS, RMQR_EC_CODEWORDS, RMQR_NUM_BLOCKS,
+    },
+    data::{BitVec, Data},
+    math::{div, exp, mul, ANTILOG_TABLE, LOG_TABLE},
+    qr_code::{Version, ECL},
+    DecodeError,
 };
 
 pub fn ecc_and_sequence(mut data: Data) -> Vec<u8> {
@@ -90,6 +95,104 @@ pub fn ecc_and_sequence(mut data: Data) -> Vec<u8> {
     final_sequence
 }
 
+/// Micro QR has a single block, so the sequence is just data then error correction.
+/// The last data codeword of M1 and M3 is 4 bits, the following codewords aren't byte aligned.
+pub fn micro_ecc_and_sequence(mut data: Data) -> Vec<u8> {
+    let version = data.version.0;
+    let capacity = MICRO_DATA_BITS[version][data.ecl as usize] as usize;
+    let num_ec_codewords = MICRO_EC_CODEWORDS[version][data.ecl as usize] as usize;
+
+    // terminator, 3/5/7/9 bits
+    let term_len = (version * 2 + 1).min(capacity - data.bits.len());
+    data.bits.push_n(0, term_len);
+
+    // byte align
+    let byte_pad = ((8 - (data.bits.len() % 8)) % 8).min(capacity - data.bits.len());
+    data.bits.push_n(0, byte_pad);
+
+    // fill data capacity
+    let mut alternating_byte = 0b11101100;
+    while data.bits.len() + 8 <= capacity {
+        data.bits.push_n(alternating_byte, 8);
+        alternating_byte ^= 0b11111101;
+    }
+    let remaining = capacity - data.bits.len();
+    data.bits.push_n(0, remaining);
+
+    // 4 bit codeword is the high half of a byte for error correction
+    let data_codewords = data.bits.to_bytes();
+    let ec_codewords = remainder(
+        &data_codewords,
+        &GEN_POLYNOMIALS[num_ec_codewords][..num_ec_codewords],
+    );
+
+    let mut sequence = BitVec::with_capacity(capacity + num_ec_codewords * 8);
+    for (i, codeword) in data_codewords.iter().enumerate() {
+        if i == data_codewords.len() - 1 && !capacity.is_multiple_of(8) {
+            sequence.push_n((codeword >> 4).into(), 4);
+        } else {
+            sequence.push_n((*codeword).into(), 8);
+        }
+    }
+    for codeword in ec_codewords {
+        sequence.push_n(codeword.into(), 8);
+    }
+    sequence.to_bytes()
+}
+
+/// Same interleaving as `ecc_and_sequence`, with rMQR's own tables.
+/// The remainder bits are included as a trailing 0 byte.
+pub fn rmqr_ecc_and_sequence(mut data: Data) -> Vec<u8> {
+    let version = data.version.0;
+    let num_data_codewords = RMQR_DATA_CODEWORDS[version][data.ecl as usize] as usize;
+    let num_ec_codewords = RMQR_EC_CODEWORDS[version][data.ecl as usize] as usize;
+    let blocks = RMQR_NUM_BLOCKS[version][data.ecl as usize] as usize;
+    let capacity = num_data_codewords * 8;
+
+    // terminator
+    let term_len = 3.min(capacity - data.bits.len());
+    data.bits.push_n(0, term_len);
+
+    // byte align
+    let byte_pad = (8 - (data.bits.len() % 8)) % 8;
+    data.bits.push_n(0, byte_pad);
+
+    // fill data capacity
+    let mut alternating_byte = 0b11101100;
+    while data.bits.len() < capacity {
+        data.bits.push_n(alternating_byte, 8);
+        alternating_byte ^= 0b11111101;
+    }
+
+    // shorter blocks come first
+    let data_codewords = data.bits.to_bytes();
+    let data_per_block = num_data_codewords / blocks;
+    let group_1_blocks = blocks - num_data_codewords % blocks;
+    let ecc_per_block = num_ec_codewords / blocks;
+
+    let mut data_blocks = Vec::with_capacity(blocks);
+    let mut start = 0;
+    for i in 0..blocks {
+        let len = data_per_block + (i >= group_1_blocks) as usize;
+        data_blocks.push(&data_codewords[start..start + len]);
+        start += len;
+    }
+    let ec_blocks: Vec<Vec<u8>> = data_blocks
+        .iter()
+        .map(|block| remainder(block, &GEN_POLYNOMIALS[ecc_per_block][..ecc_per_block]))
+        .collect();
+
+    let mut final_sequence = Vec::with_capacity(num_data_codewords + num_ec_codewords + 1);
+    for i in 0..=data_per_block {
+        final_sequence.extend(data_blocks.iter().filter_map(|block| block.get(i)));
+    }
+    for i in 0..ecc_per_block {
+        final_sequence.extend(ec_blocks.iter().map(|block| block[i]));
+    }
+    final_sequence.push(0);
+    final_sequence
+}
+
 // todo
 // benchmark potential optimizations
 pub fn remainder(data: &[u8], generator: &[u8]) -> Vec<u8> {
@@ -114,3 +217,297 @@ pub fn remainder(data: &[u8], generator: &[u8]) -> Vec<u8> {
 
     base[data.len()..(data.len() + num_codewords)].to_vec()
 }
+
+/// Splits the final codeword sequence back into error correction blocks,
+/// each block being its data codewords followed by its ec codewords
+pub fn deinterleave(sequence: &[u8], version: Version, ecl: ECL) -> Vec<Vec<u8>> {
+    let codewords = NUM_DATA_MODULES[version.0] as usize / 8;
+
+    let num_ec_codewords = NUM_EC_CODEWORDS[version.0][ecl as usize] as usize;
+    let num_data_codewords = codewords - num_ec_codewords;
+
+    let blocks = NUM_BLOCKS[version.0][ecl as usize] as usize;
+
+    let group_2_blocks = codewords % blocks;
+    let group_1_blocks = blocks - group_2_blocks;
+
+    let data_per_g1_block = num_data_codewords / blocks;
+    let ecc_per_block = num_ec_codewords / blocks;
+
+    let mut result = vec![Vec::with_capacity(data_per_g1_block + 1 + ecc_per_block); blocks];
+    let mut i = 0;
+
+    for col in 0..data_per_g1_block + 1 {
+        for (row, block) in result.iter_mut().enumerate() {
+            // only group 2 blocks have the last column
+            if col == data_per_g1_block && row < group_1_blocks {
+                continue;
+            }
+            block.push(sequence[i]);
+            i += 1;
+        }
+    }
+    for _ in 0..ecc_per_block {
+        for block in result.iter_mut() {
+            block.push(sequence[i]);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+/// Corrects one error correction block in place, `block` being its data codewords followed by
+/// `num_ec_codewords` ec codewords. `erasures` are indices into `block` of codewords known
+/// to be unreliable, each costs half as much correction capacity as an unknown error.
+///
+/// Returns the number of codewords changed, or `DecodeError::Uncorrectable` (leaving `block`
+/// untouched) if `2 * errors + erasures > num_ec_codewords`, or an erasure is out of range or repeated.
+pub fn correct(
+    block: &mut [u8],
+    num_ec_codewords: usize,
+    erasures: &[usize],
+) -> Result<usize, DecodeError> {
+    let mut erased = vec![false; block.len()];
+    for &i in erasures {
+        if i >= block.len() || erased[i] {
+            return Err(DecodeError::Uncorrectable);
+        }
+        erased[i] = true;
+    }
+
+    let syndrome_poly = syndromes(block, num_ec_codewords);
+    if syndrome_poly.iter().all(|&s| s == 0) {
+        return Ok(0);
+    }
+    if erasures.len() > num_ec_codewords {
+        return Err(DecodeError::Uncorrectable);
+    }
+
+    // first codeword is the coefficient of the highest degree
+    let len = block.len();
+    let locator = |i: usize| (len - 1 - i) as isize;
+
+    let mut erasure_poly = vec![1];
+    for &i in erasures {
+        erasure_poly = poly_mul(&erasure_poly, &[1, exp(locator(i))]);
+    }
+
+    let mut error_poly = berlekamp_massey(&syndrome_poly, erasure_poly, erasures.len());
+    while error_poly.len() > 1 && error_poly[error_poly.len() - 1] == 0 {
+        error_poly.pop();
+    }
+    let degree = error_poly.len() - 1;
+    if degree < erasures.len() || 2 * degree - erasures.len() > num_ec_codewords {
+        return Err(DecodeError::Uncorrectable);
+    }
+
+    // chien search
+    let positions: Vec<usize> = (0..len)
+        .filter(|&i| poly_eval(&error_poly, exp(-locator(i))) == 0)
+        .collect();
+    if positions.len() != degree {
+        return Err(DecodeError::Uncorrectable);
+    }
+
+    // forney, generator polynomial starts at a^0
+    let mut evaluator = poly_mul(&syndrome_poly, &error_poly);
+    evaluator.truncate(num_ec_codewords);
+    let derivative: Vec<u8> = error_poly
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+        .collect();
+
+    let mut corrected = block.to_vec();
+    let mut changed = 0;
+    for &i in &positions {
+        let x_inv = exp(-locator(i));
+        let denominator = poly_eval(&derivative, x_inv);
+        if denominator == 0 {
+            return Err(DecodeError::Uncorrectable);
+        }
+        let magnitude = mul(
+            exp(locator(i)),
+            div(poly_eval(&evaluator, x_inv), denominator),
+        );
+        if magnitude != 0 {
+            corrected[i] ^= magnitude;
+            changed += 1;
+        }
+    }
+
+    if syndromes(&corrected, num_ec_codewords)
+        .iter()
+        .any(|&s| s != 0)
+    {
+        return Err(DecodeError::Uncorrectable);
+    }
+    block.copy_from_slice(&corrected);
+    Ok(changed)
+}
+
+/// Block evaluated at a^0 to a^(num_ec_codewords - 1), all 0 iff no errors
+pub fn syndromes(block: &[u8], num_ec_codewords: usize) -> Vec<u8> {
+    (0..num_ec_codewords)
+        .map(|j| {
+            let alpha = exp(j as isize);
+            block.iter().fold(0, |acc, &c| mul(acc, alpha) ^ c)
+        })
+        .collect()
+}
+
+/// Returns the error locator, starting from the erasure locator when erasures are known
+fn berlekamp_massey(syndromes: &[u8], erasure_poly: Vec<u8>, num_erasures: usize) -> Vec<u8> {
+    let mut locator = erasure_poly.clone();
+    let mut prev = erasure_poly;
+    let mut len = num_erasures;
+
+    for n in num_erasures..syndromes.len() {
+        let mut delta = 0;
+        for (i, &c) in locator.iter().enumerate().take(n + 1) {
+            delta ^= mul(c, syndromes[n - i]);
+        }
+
+        prev.insert(0, 0);
+        if delta == 0 {
+            continue;
+        }
+
+        let scaled: Vec<u8> = prev.iter().map(|&c| mul(c, delta)).collect();
+        if 2 * len <= n + num_erasures {
+            prev = locator.iter().map(|&c| div(c, delta)).collect();
+            len = n + 1 + num_erasures - len;
+        }
+        locator = poly_add(&locator, &scaled);
+    }
+
+    locator
+}
+
+// polynomials below are stored lowest degree first
+
+fn poly_add(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; a.len().max(b.len())];
+    for (i, &c) in a.iter().enumerate() {
+        result[i] ^= c;
+    }
+    for (i, &c) in b.iter().enumerate() {
+        result[i] ^= c;
+    }
+    result
+}
+
+fn poly_mul(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; a.len() + b.len() - 1];
+    for (i, &x) in a.iter().enumerate() {
+        for (j, &y) in b.iter().enumerate() {
+            result[i + j] ^= mul(x, y);
+        }
+    }
+    result
+}
+
+fn poly_eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &c| mul(acc, x) ^ c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(data_len: usize, num_ec_codewords: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        let mut block: Vec<u8> = (0..data_len)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let mut ecc = remainder(
+            &block,
+            &GEN_POLYNOMIALS[num_ec_codewords][..num_ec_codewords],
+        );
+        block.append(&mut ecc);
+        block
+    }
+
+    #[test]
+    fn correct_clean_block() {
+        let mut b = block(19, 7, 1);
+        assert_eq!(correct(&mut b, 7, &[]), Ok(0));
+    }
+
+    #[test]
+    fn correct_errors() {
+        for (data_len, num_ec) in [(19, 7), (16, 10), (13, 13), (9, 17), (15, 30), (121, 30)] {
+            for errors in 1..=num_ec / 2 {
+                let original = block(data_len, num_ec, errors as u32);
+                let mut b = original.clone();
+                let len = b.len();
+                for e in 0..errors {
+                    b[(e * 7 + 3) % len] ^= (e as u8).wrapping_mul(37) | 1;
+                }
+                assert_eq!(correct(&mut b, num_ec, &[]), Ok(errors));
+                assert_eq!(b, original);
+            }
+        }
+    }
+
+    #[test]
+    fn correct_erasures() {
+        for (data_len, num_ec) in [(19, 7), (16, 10), (9, 17), (15, 30)] {
+            let original = block(data_len, num_ec, 42);
+
+            // erasures only, up to the full ec capacity
+            let mut b = original.clone();
+            let erasures: Vec<usize> = (0..num_ec).map(|i| (i * 7) % b.len()).collect();
+            for &i in &erasures {
+                b[i] = !b[i];
+            }
+            assert_eq!(correct(&mut b, num_ec, &erasures), Ok(num_ec));
+            assert_eq!(b, original);
+
+            // mixed, 2 * errors + erasures == num_ec
+            let mut b = original.clone();
+            let num_erasures = num_ec % 2 + 2;
+            let erasures: Vec<usize> = (0..num_erasures).collect();
+            for &i in &erasures {
+                b[i] ^= 0x5a;
+            }
+            // an erasure that is actually correct is fine too
+            let erasures: Vec<usize> = (0..num_erasures + 1).collect();
+            let last = b.len() - 1;
+            for e in 0..(num_ec - erasures.len()) / 2 {
+                b[last - e * 2] ^= 0xff;
+            }
+            assert!(correct(&mut b, num_ec, &erasures).is_ok());
+            assert_eq!(b, original);
+        }
+    }
+
+    #[test]
+    fn correct_rejects_invalid_erasures() {
+        let original = block(19, 7, 3);
+        let mut b = original.clone();
+        b[2] ^= 0x11;
+        assert_eq!(correct(&mut b, 7, &[26]), Err(DecodeError::Uncorrectable));
+        assert_eq!(correct(&mut b, 7, &[2, 2]), Err(DecodeError::Uncorrectable));
+        assert_eq!(b[2], original[2] ^ 0x11);
+        assert_eq!(correct(&mut b, 7, &[2]), Ok(1));
+        assert_eq!(b, original);
+    }
+
+    #[test]
+    fn correct_too_many_errors() {
+        let original = block(9, 17, 7);
+        let mut b = original.clone();
+        for i in 0..9 {
+            b[i * 2] ^= 0xa5;
+        }
+        assert_eq!(correct(&mut b, 17, &[]), Err(DecodeError::Uncorrectable));
+        assert_ne!(b, original);
+        assert_eq!(b[0], original[0] ^ 0xa5);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    data::Data,
+    encoding::{Header, GS},
+    qr_code::{Fnc1, Version, ECL},
+    Gs1Error,
+};
+use Format::*;
+
+#[derive(Clone, Copy)]
+enum Format {
+    Numeric,
+    /// YYMMDD, DD can be 00 for the end of the month
+    Date,
+    /// GS1 AI encodable character set 82
+    Alphanumeric,
+}
+
+struct Ai {
+    /// 'n' matches any digit, e.g. the decimal point position of 310n
+    ai: &'static str,
+    format: Format,
+    min: usize,
+    max: usize,
+    check_digit: bool,
+}
+
+const fn ai(ai: &'static str, format: Format, min: usize, max: usize, check_digit: bool) -> Ai {
+    Ai {
+        ai,
+        format,
+        min,
+        max,
+        check_digit,
+    }
+}
+
+/// Commonly used Application Identifiers
+const AIS: &[Ai] = &[
+    ai("00", Numeric, 18, 18, true),        // SSCC
+    ai("01", Numeric, 14, 14, true),        // GTIN
+    ai("02", Numeric, 14, 14, true),        // CONTENT
+    ai("10", Alphanumeric, 1, 20, false),   // BATCH/LOT
+    ai("11", Date, 6, 6, false),            // PROD DATE
+    ai("12", Date, 6, 6, false),            // DUE DATE
+    ai("13", Date, 6, 6, false),            // PACK DATE
+    ai("15", Date, 6, 6, false),            // BEST BEFORE
+    ai("16", Date, 6, 6, false),            // SELL BY
+    ai("17", Date, 6, 6, false),            // USE BY/EXPIRY
+    ai("20", Numeric, 2, 2, false),         // VARIANT
+    ai("21", Alphanumeric, 1, 20, false),   // SERIAL
+    ai("22", Alphanumeric, 1, 20, false),   // CPV
+    ai("240", Alphanumeric, 1, 30, false),  // ADDITIONAL ID
+    ai("241", Alphanumeric, 1, 30, false),  // CUST. PART No.
+    ai("250", Alphanumeric, 1, 30, false),  // SECONDARY SERIAL
+    ai("30", Numeric, 1, 8, false),         // VAR. COUNT
+    ai("310n", Numeric, 6, 6, false),       // NET WEIGHT (kg)
+    ai("311n", Numeric, 6, 6, false),       // LENGTH (m)
+    ai("312n", Numeric, 6, 6, false),       // WIDTH (m)
+    ai("313n", Numeric, 6, 6, false),       // HEIGHT (m)
+    ai("314n", Numeric, 6, 6, false),       // AREA (m2)
+    ai("315n", Numeric, 6, 6, false),       // NET VOLUME (l)
+    ai("316n", Numeric, 6, 6, false),       // NET VOLUME (m3)
+    ai("37", Numeric, 1, 8, false),         // COUNT
+    ai("390n", Numeric, 1, 15, false),      // AMOUNT
+    ai("392n", Numeric, 1, 15, false),      // PRICE
+    ai("400", Alphanumeric, 1, 30, false),  // ORDER NUMBER
+    ai("401", Alphanumeric, 1, 30, false),  // GINC
+    ai("410", Numeric, 13, 13, true),       // SHIP TO LOC
+    ai("414", Numeric, 13, 13, true),       // LOC No.
+    ai("420", Alphanumeric, 1, 20, false),  // SHIP TO POST
+    ai("422", Numeric, 3, 3, false),        // ORIGIN
+    ai("7003", Numeric, 10, 10, false),     // EXPIRY TIME
+    ai("8004", Alphanumeric, 1, 30, false), // GIAI
+    ai("8200", Alphanumeric, 1, 70, false), // PRODUCT URL
+    ai("90", Alphanumeric, 1, 30, false),   // INTERNAL
+    ai("91", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("92", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("93", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("94", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("95", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("96", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("97", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("98", Alphanumeric, 1, 90, false),   // INTERNAL
+    ai("99", Alphanumeric, 1, 90, false),   // INTERNAL
+];
+
+/// AIs starting with these don't need a GS after them, even if they aren't last
+const PREDEFINED_LENGTH: [&str; 22] = [
+    "00", "01", "02", "03", "04", "11", "12", "13", "14", "15", "16", "17", "18", "19", "20", "31",
+    "32", "33", "34", "35", "36", "41",
+];
+
+/// GS1 element string builder, checks AI formats and check digits as fields are added
+#[derive(Clone, Debug, Default)]
+pub struct ElementString {
+    text: String,
+    /// last field has a variable length
+    needs_gs: bool,
+}
+
+impl ElementString {
+    pub fn new() -> Self {
+        ElementString::default()
+    }
+
+    /// Appends a field, e.g. `add("01", "09506000134352")` for a GTIN
+    pub fn add(mut self, ai: &str, value: &str) -> Result<Self, Gs1Error> {
+        let spec = AIS
+            .iter()
+            .find(|spec| {
+                spec.ai.len() == ai.len()
+                    && spec
+                        .ai
+                        .bytes()
+                        .zip(ai.bytes())
+                        .all(|(s, a)| s == a || (s == b'n' && a.is_ascii_digit()))
+            })
+            .ok_or(Gs1Error::UnknownAi)?;
+
+        if value.len() < spec.min || value.len() > spec.max {
+            return Err(Gs1Error::InvalidLength);
+        }
+        let valid_chars = match spec.format {
+            Numeric | Date => value.bytes().all(|b| b.is_ascii_digit()),
+            Alphanumeric => value.bytes().all(is_cset82),
+        };
+        if !valid_chars {
+            return Err(Gs1Error::InvalidCharacter);
+        }
+        if let Date = spec.format {
+            let month = &value[2..4];
+            let day = &value[4..6];
+            if !("01"..="12").contains(&month) || day > "31" {
+                return Err(Gs1Error::InvalidDate);
+            }
+        }
+        if spec.check_digit {
+            let (digits, check) = value.split_at(value.len() - 1);
+            if check_digit(digits) != Some(check.as_bytes()[0] - b'0') {
+                return Err(Gs1Error::InvalidCheckDigit);
+            }
+        }
+
+        if self.needs_gs {
+            self.text.push(GS);
+        }
+        self.text.push_str(ai);
+        self.text.push_str(value);
+        self.needs_gs = !PREDEFINED_LENGTH.contains(&&ai[..2]);
+        Ok(self)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Same as `Data::new_segmented`, with FNC1 in first position
+    pub fn to_data(
+        &self,
+        min_version: Version,
+        strict_version: bool,
+        min_ecl: ECL,
+        strict_ecl: bool,
+    ) -> Option<Data> {
+        let header = Header {
+            fnc1: Some(Fnc1::First),
+            ..Header::default()
+        };
+        Data::new_segmented(
+            &self.text,
+            header,
+            min_version,
+            strict_version,
+            min_ecl,
+            strict_ecl,
+        )
+    }
+}
+
+/// Mod 10 check digit of GTINs, SSCCs, GLNs, etc. None if `digits` has anything but ASCII digits.
+pub fn check_digit(digits: &str) -> Option<u8> {
+    let mut sum = 0;
+    for (i, b) in digits.bytes().rev().enumerate() {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        sum += (b - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 };
+    }
+    Some(((10 - sum % 10) % 10) as u8)
+}
+
+fn is_cset82(b: u8) -> bool {
+    matches!(b, b'!'..=b'"' | b'%'..=b'?' | b'A'..=b'Z' | b'_' | b'a'..=b'z')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode::decode, qr_code::QrCode};
+
+    #[test]
+    fn check_digit_works() {
+        assert_eq!(check_digit("0950600013435"), Some(2));
+        assert_eq!(check_digit("629104150021"), Some(3));
+        assert_eq!(check_digit("0"), Some(0));
+        assert_eq!(check_digit("12 4"), None);
+        assert_eq!(check_digit("12a"), None);
+    }
+
+    #[test]
+    fn element_string_works() {
+        let elements = ElementString::new()
+            .add("01", "09506000134352")
+            .unwrap()
+            .add("10", "ABC%123")
+            .unwrap()
+            .add("3103", "000189")
+            .unwrap()
+            .add("17", "251200")
+            .unwrap()
+            .add("21", "X1")
+            .unwrap();
+        assert_eq!(
+            elements.as_str(),
+            "010950600013435210ABC%123\x1d31030001891725120021X1"
+        );
+    }
+
+    #[test]
+    fn element_string_rejects_invalid() {
+        let add = |ai, value| ElementString::new().add(ai, value).unwrap_err();
+        assert_eq!(add("01", "09506000134353"), Gs1Error::InvalidCheckDigit);
+        assert_eq!(add("01", "0950600013435"), Gs1Error::InvalidLength);
+        assert_eq!(add("10", ""), Gs1Error::InvalidLength);
+        assert_eq!(add("10", "ÄBC"), Gs1Error::InvalidCharacter);
+        assert_eq!(add("30", "12A"), Gs1Error::InvalidCharacter);
+        assert_eq!(add("17", "251301"), Gs1Error::InvalidDate);
+        assert_eq!(add("17", "251232"), Gs1Error::InvalidDate);
+        assert_eq!(add("319", "000189"), Gs1Error::UnknownAi);
+        assert_eq!(add("3173", "000189"), Gs1Error::UnknownAi);
+    }
+
+    #[test]
+    fn element_string_round_trips() {
+        let elements = ElementString::new()
+            .add("01", "09506000134352")
+            .unwrap()
+            .add("10", "LOT%42")
+            .unwrap()
+            .add("17", "251231")
+            .unwrap();
+        let data = elements
+            .to_data(Version(1), false, ECL::Low, false)
+            .unwrap();
+        let decoded = decode(&QrCode::new(data, None).matrix).unwrap();
+        assert_eq!(decoded.fnc1, Some(Fnc1::First));
+        assert_eq!(decoded.text, elements.as_str());
+    }
+}
@@ -12,14 +12,18 @@ pub mod qr_code;
 pub mod bit_info;
 pub mod qart;
 
+pub mod decode;
+pub mod gs1;
+
 pub mod render;
 
 #[cfg(feature = "wasm")]
 mod wasm;
 
 use crate::data::Data;
-use crate::qr_code::{Mask, Mode, Version, ECL};
-use encoding::encoding_mode;
+use crate::qr_code::{Fnc1, Mask, Mode, Version, ECL, MICRO_MASKS};
+use encoding::{is_encodable, Header, Segment, StructuredAppend, ECI_UTF8};
+use mask::MaskSelector;
 use qart::{Qart, WeightPixel};
 use qr_code::QrCode;
 
@@ -35,6 +39,9 @@ pub struct QrOptions {
     mask: Option<Mask>,
     strict_version: bool,
     strict_ecl: bool,
+    eci: Option<u32>,
+    fnc1: Option<Fnc1>,
+    mask_selector: Option<Box<dyn MaskSelector>>,
 }
 
 #[cfg_attr(feature = "wasm", wasm_bindgen)]
@@ -48,6 +55,9 @@ impl QrOptions {
             strict_ecl: false,
             mode: None,
             mask: None,
+            eci: None,
+            fnc1: None,
+            mask_selector: None,
         }
     }
     pub fn min_version(mut self, version: Version) -> Self {
@@ -74,6 +84,27 @@ impl QrOptions {
         self.strict_ecl = strict;
         self
     }
+    /// ECI assignment number written before the data, None = UTF-8 (26) if input isn't ASCII
+    pub fn eci(mut self, eci: Option<u32>) -> Self {
+        self.eci = eci;
+        self
+    }
+}
+
+// not representable in wasm
+impl QrOptions {
+    /// FNC1 mode written before the data, GS (0x1D) ends variable length fields.
+    /// Invalid application indicators (see `Fnc1::is_valid`) are an `InvalidEncoding` error.
+    pub fn fnc1(mut self, fnc1: Option<Fnc1>) -> Self {
+        self.fnc1 = fnc1;
+        self
+    }
+    /// Picks the mask when `mask` is None, see `mask::Spec`, `mask::Fastest` and `mask::TargetImage`.
+    /// Micro QR and rMQR have their own mask rules, `generate_micro` and `generate_rmqr` return `InvalidMask` if it's set.
+    pub fn mask_selector(mut self, selector: impl MaskSelector + 'static) -> Self {
+        self.mask_selector = Some(Box::new(selector));
+        self
+    }
 }
 
 #[cfg_attr(feature = "wasm", wasm_bindgen)]
@@ -81,11 +112,15 @@ impl QrOptions {
 pub enum QrError {
     InvalidEncoding,
     ExceedsMaxCapacity,
+    /// Not one of `MICRO_MASKS` for Micro QR, or a `mask_selector` for Micro QR or rMQR
+    InvalidMask,
+    /// `mask::TargetImage` doesn't match the size of the symbol
+    InvalidPixelWeights,
 }
 
 pub fn generate(input: &str, qr_options: &QrOptions) -> Result<QrCode, QrError> {
-    match resolve_data(input, qr_options) {
-        Ok(data) => Ok(QrCode::new(data, qr_options.mask)),
+    match resolve_data(input, qr_options, None) {
+        Ok(data) => new_qr_code(data, qr_options),
         Err(err) => Err(err),
     }
 }
@@ -96,6 +131,7 @@ pub enum QartError {
     InvalidEncoding,
     ExceedsMaxCapacity,
     InvalidPixelWeights,
+    InvalidMask,
 }
 
 impl From<QrError> for QartError {
@@ -103,8 +139,138 @@ impl From<QrError> for QartError {
         match value {
             QrError::InvalidEncoding => QartError::InvalidEncoding,
             QrError::ExceedsMaxCapacity => QartError::ExceedsMaxCapacity,
+            QrError::InvalidMask => QartError::InvalidMask,
+            QrError::InvalidPixelWeights => QartError::InvalidPixelWeights,
+        }
+    }
+}
+
+/// Splits `input` across the fewest Structured Append symbols (max 16) that fit `qr_options`.
+/// Use `strict_version` and `strict_ecl` to make every symbol the same `Version` and `ECL`.
+pub fn generate_structured(input: &str, qr_options: &QrOptions) -> Result<Vec<QrCode>, QrError> {
+    structured(input, |_| qr_options)
+}
+
+/// Same as `generate_structured`, with `symbol_options[i]` for the i-th symbol, e.g. to pick a `Version` or `ECL`
+/// per symbol. Symbols past the end of `symbol_options` use `qr_options`.
+pub fn generate_structured_per_symbol(
+    input: &str,
+    qr_options: &QrOptions,
+    symbol_options: &[QrOptions],
+) -> Result<Vec<QrCode>, QrError> {
+    structured(input, |index| {
+        symbol_options.get(index).unwrap_or(qr_options)
+    })
+}
+
+fn structured<'a>(
+    input: &str,
+    options: impl Fn(usize) -> &'a QrOptions,
+) -> Result<Vec<QrCode>, QrError> {
+    let placeholder = StructuredAppend {
+        index: 0,
+        total: 16,
+        parity: 0,
+    };
+
+    // longest prefix that fits, on char boundaries so each symbol is readable by itself
+    let mut chunks = Vec::new();
+    let mut rest = input;
+    loop {
+        let qr_options = options(chunks.len());
+        let fits = |text: &str| resolve_data(text, qr_options, Some(placeholder)).is_ok();
+        let ends: Vec<usize> = rest.char_indices().map(|(i, c)| i + c.len_utf8()).collect();
+        let fit = ends.partition_point(|&end| fits(&rest[..end]));
+        if fit == ends.len() {
+            chunks.push(rest);
+            break;
+        }
+        if fit == 0 {
+            // InvalidEncoding if the next char doesn't match the mode
+            resolve_data(&rest[..ends[0]], qr_options, Some(placeholder))?;
+        }
+        if fit == 0 || chunks.len() == 15 {
+            return Err(QrError::ExceedsMaxCapacity);
+        }
+        chunks.push(&rest[..ends[fit - 1]]);
+        rest = &rest[ends[fit - 1]..];
+    }
+
+    let parity = StructuredAppend::parity(input);
+    let total = chunks.len() as u8;
+    let mut qr_codes = Vec::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let header = StructuredAppend {
+            index: index as u8,
+            total,
+            parity,
+        };
+        let qr_options = options(index);
+        let data = resolve_data(chunk, qr_options, Some(header))?;
+        qr_codes.push(new_qr_code(data, qr_options)?);
+    }
+    Ok(qr_codes)
+}
+
+/// Micro QR, `Version(1..=4)` is M1-M4 and `ECL::High` doesn't exist.
+/// `mask` must be one of `MICRO_MASKS`, or None to determine it automatically. `mask_selector` must not be set.
+pub fn generate_micro(input: &str, qr_options: &QrOptions) -> Result<QrCode, QrError> {
+    // Micro QR has no ECI, Structured Append or FNC1
+    if qr_options.eci.is_some() || qr_options.fnc1.is_some() {
+        return Err(QrError::InvalidEncoding);
+    }
+    if qr_options.mask_selector.is_some()
+        || qr_options
+            .mask
+            .is_some_and(|mask| !MICRO_MASKS.contains(&mask))
+    {
+        return Err(QrError::InvalidMask);
+    }
+    if let Some(mode) = qr_options.mode {
+        if !input.chars().all(|c| is_encodable(c, mode, false)) {
+            return Err(QrError::InvalidEncoding);
+        }
+    }
+
+    let data = Data::new_micro(
+        input,
+        qr_options.mode,
+        qr_options.min_version,
+        qr_options.strict_version,
+        qr_options.min_ecl,
+        qr_options.strict_ecl,
+    )
+    .ok_or(QrError::ExceedsMaxCapacity)?;
+    Ok(QrCode::new(data, qr_options.mask))
+}
+
+/// rMQR, `Version(1..=32)` is R7x43 ... R17x139 and only `ECL::Medium` and `ECL::High` exist.
+/// Unless `strict_version`, the symbol keeps the height of `min_version` and only gets wider.
+/// rMQR always uses mask M4, `mask_selector` must not be set.
+pub fn generate_rmqr(input: &str, qr_options: &QrOptions) -> Result<QrCode, QrError> {
+    // not supported yet
+    if qr_options.eci.is_some() || qr_options.fnc1.is_some() {
+        return Err(QrError::InvalidEncoding);
+    }
+    if qr_options.mask_selector.is_some() {
+        return Err(QrError::InvalidMask);
+    }
+    if let Some(mode) = qr_options.mode {
+        if !input.chars().all(|c| is_encodable(c, mode, false)) {
+            return Err(QrError::InvalidEncoding);
         }
     }
+
+    let data = Data::new_rmqr(
+        input,
+        qr_options.mode,
+        qr_options.min_version,
+        qr_options.strict_version,
+        qr_options.min_ecl,
+        qr_options.strict_ecl,
+    )
+    .ok_or(QrError::ExceedsMaxCapacity)?;
+    Ok(QrCode::new(data, None))
 }
 
 pub fn generate_qart(
@@ -112,10 +278,7 @@ pub fn generate_qart(
     qr_options: &QrOptions,
     pixel_weights: &[WeightPixel],
 ) -> Result<QrCode, QartError> {
-    let data = match resolve_data(input, qr_options) {
-        Ok(data) => data,
-        Err(err) => return Err(err.into()),
-    };
+    let data = resolve_data(input, qr_options, None)?;
 
     let qr_width = data.version.0 * 4 + 17;
     if pixel_weights.len() != qr_width * qr_width {
@@ -127,29 +290,138 @@ pub fn generate_qart(
     Ok(qr_code)
 }
 
-fn resolve_data(input: &str, qr_options: &QrOptions) -> Result<Data, QrError> {
-    let mut mode = Mode::Byte;
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    InvalidWidth,
+    InvalidVersion,
+    InvalidFormat,
+    Uncorrectable,
+    InvalidMode,
+    InvalidData,
+    /// Structured Append symbols are missing or from different sequences
+    InvalidSequence,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gs1Error {
+    UnknownAi,
+    InvalidLength,
+    InvalidCharacter,
+    InvalidDate,
+    InvalidCheckDigit,
+}
+
+#[cfg(feature = "raster")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RasterError {
+    InvalidSvg,
+    /// width or height is 0
+    InvalidSize,
+    EncodingFailed,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogoError {
+    /// Empty or larger than the symbol
+    InvalidSize,
+    /// Only normal QR codes are checked
+    UnsupportedSymbol,
+    /// Hides more codewords of a block than error correction can fix
+    ExceedsErrorCorrection,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContrastError {
+    /// Not `#rgb` or `#rrggbb`, or an image fill
+    InvalidColor,
+    LowContrast,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SizeError {
+    /// Not a positive number, or too small for a single pixel per module
+    InvalidSize,
+}
+
+/// `render_png`, `render_pdf`, `render_eps` and `render_ansi` parse colors themselves and never fall back to others
+#[cfg(feature = "render")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderError {
+    /// A color the renderer can't parse, e.g. anything but `#rgb` or `#rrggbb` for PNG
+    InvalidColor,
+    /// `module_size` isn't a positive number
+    InvalidSize,
+}
+
+fn new_qr_code(data: Data, qr_options: &QrOptions) -> Result<QrCode, QrError> {
+    match (qr_options.mask, &qr_options.mask_selector) {
+        (None, Some(selector)) => match selector.fits(data.version.0 * 4 + 17) {
+            true => Ok(QrCode::new_with_selector(data, selector.as_ref())),
+            false => Err(QrError::InvalidPixelWeights),
+        },
+        (mask, _) => Ok(QrCode::new(data, mask)),
+    }
+}
 
-    if let Some(specified) = qr_options.mode {
-        if specified != Mode::Byte {
-            let lowest = encoding_mode(input);
-            if (lowest as u8) > (specified as u8) {
-                return Err(QrError::InvalidEncoding);
-            }
-            mode = specified;
+fn resolve_data(
+    input: &str,
+    qr_options: &QrOptions,
+    structured_append: Option<StructuredAppend>,
+) -> Result<Data, QrError> {
+    if qr_options.fnc1.is_some_and(|fnc1| !fnc1.is_valid()) {
+        return Err(QrError::InvalidEncoding);
+    }
+    let fnc1 = qr_options.fnc1.is_some();
+    if let Some(mode) = qr_options.mode {
+        if !input.chars().all(|c| is_encodable(c, mode, fnc1)) {
+            return Err(QrError::InvalidEncoding);
         }
-    } else {
-        mode = encoding_mode(input);
     }
 
-    let data = Data::new_verbose(
-        input,
-        mode,
-        qr_options.min_version,
-        qr_options.strict_version,
-        qr_options.min_ecl,
-        qr_options.strict_ecl,
-    );
+    // raw UTF-8 would be read as the ISO-8859-1 default otherwise,
+    // without `mode` it depends on the segments, see `Data::new_segmented`
+    let eci = match qr_options.eci {
+        Some(eci) if eci > 999999 => return Err(QrError::InvalidEncoding),
+        Some(eci) => Some(eci),
+        None => (qr_options.mode == Some(Mode::Byte) && !input.is_ascii()).then_some(ECI_UTF8),
+    };
+
+    let header = Header {
+        structured_append,
+        eci,
+        fnc1: qr_options.fnc1,
+    };
+    let data = match qr_options.mode {
+        Some(mode) => {
+            let segment = match fnc1 {
+                true => Segment::new_fnc1(mode, input),
+                false => Segment::new(mode, input),
+            };
+            Data::from_segments(
+                &[segment],
+                header,
+                qr_options.min_version,
+                qr_options.strict_version,
+                qr_options.min_ecl,
+                qr_options.strict_ecl,
+            )
+        }
+        None => Data::new_segmented(
+            input,
+            header,
+            qr_options.min_version,
+            qr_options.strict_version,
+            qr_options.min_ecl,
+            qr_options.strict_ecl,
+        ),
+    };
 
     match data {
         Some(x) => Ok(x),
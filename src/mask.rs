@@ -1,32 +1,124 @@
-use crate::matrix::{Matrix, Module};
+use std::{
+    fmt::Debug,
+    panic::{RefUnwindSafe, UnwindSafe},
+};
 
-// todo UNTESTED CODE: HERE BE DRAGONS
-// if score is wrong for all masks, then this still works
+use crate::{
+    matrix::{Matrix, Module},
+    qart::WeightPixel,
+    qr_code::Mask,
+};
+
+/// Picks the mask of a QR code. Each candidate is scored fully masked, with its format info,
+/// and the lowest score wins, the first one if tied.
+/// Thread and unwind safe so `QrOptions` holding one stays `Send + Sync`.
+pub trait MaskSelector: Debug + Send + Sync + UnwindSafe + RefUnwindSafe {
+    fn score(&self, matrix: &Matrix<Module>) -> u32;
+
+    /// A mask to use without scoring any candidate
+    fn fixed_mask(&self) -> Option<Mask> {
+        None
+    }
+
+    /// Whether it can score a symbol `width` modules wide, checked before generating
+    fn fits(&self, _width: usize) -> bool {
+        true
+    }
+}
+
+/// ISO/IEC 18004 penalty score, the default
+#[derive(Clone, Copy, Debug)]
+pub struct Spec;
+
+impl MaskSelector for Spec {
+    fn score(&self, matrix: &Matrix<Module>) -> u32 {
+        score(matrix)
+    }
+}
+
+/// Always M0, skips scoring
+#[derive(Clone, Copy, Debug)]
+pub struct Fastest;
+
+impl MaskSelector for Fastest {
+    fn score(&self, _: &Matrix<Module>) -> u32 {
+        0
+    }
+
+    fn fixed_mask(&self) -> Option<Mask> {
+        Some(Mask::M0)
+    }
+}
+
+/// Matches a target image, the score is the total weight of modules that differ from it.
+/// Same layout as `generate_qart`'s pixel weights, row major without a quiet zone, otherwise
+/// generating fails with `QrError::InvalidPixelWeights`.
+#[derive(Clone, Debug)]
+pub struct TargetImage {
+    pub pixel_weights: Vec<WeightPixel>,
+}
+
+impl MaskSelector for TargetImage {
+    fn score(&self, matrix: &Matrix<Module>) -> u32 {
+        matrix
+            .value
+            .iter()
+            .zip(&self.pixel_weights)
+            .filter(|(module, pixel)| module.has(Module::ON) != pixel.value())
+            .map(|(_, pixel)| pixel.weight() as u32)
+            .sum()
+    }
+
+    fn fits(&self, width: usize) -> bool {
+        self.pixel_weights.len() == width * width
+    }
+}
+
+/// ISO/IEC 18004 penalty score components
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Penalty {
+    /// streaks of 5+ modules of the same color
+    pub n1: u32,
+    /// 2x2 blocks of the same color
+    pub n2: u32,
+    /// finder-like patterns
+    pub n3: u32,
+    /// dark module proportion
+    pub n4: u32,
+}
+
+impl Penalty {
+    pub fn total(&self) -> u32 {
+        self.n1 + self.n2 + self.n3 + self.n4
+    }
+}
+
+/// Penalties indexed by `Mask as usize`, `best` is the automatic choice
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaskPenalties {
+    pub penalties: [Penalty; 8],
+    pub best: Mask,
+}
+
+/// ISO/IEC 18004 penalty score N1 + N2 + N3 + N4, lower is better
 pub fn score(matrix: &Matrix<Module>) -> u32 {
+    penalty(matrix).total()
+}
+
+pub fn penalty(matrix: &Matrix<Module>) -> Penalty {
     // todo what are perf implications of scoring all masks
     // 8 masks * 5 iterations (blocks + rows are non sequential access)
 
+    // N4, 10 points for every full 5% the dark modules deviate from 50%
     fn dark_proportion(matrix: &Matrix<Module>) -> u32 {
-        let mut dark = 0;
-        for y in 0..matrix.width {
-            for x in 0..matrix.width {
-                if matrix.get(x, y).has(Module::DATA) {
-                    dark += 1;
-                }
-            }
-        }
+        let total = matrix.value.len() as u32;
+        let dark = matrix.value.iter().filter(|m| m.has(Module::ON)).count() as u32;
 
-        let percent = (dark * 20) / (20 * (matrix.width as u32) * (matrix.width as u32));
-        let middle = 50;
-        let diff = if percent < middle {
-            middle - percent
-        } else {
-            percent - middle
-        };
-        let k = (diff) / 5;
+        let k = (dark * 2).abs_diff(total) * 10 / total;
         10 * k
     }
 
+    // N2, 3 points for every 2x2 block of the same color, overlaps included
     fn blocks(matrix: &Matrix<Module>) -> u32 {
         let mut score = 0;
         for y in 0..matrix.width - 1 {
@@ -43,26 +135,20 @@ pub fn score(matrix: &Matrix<Module>) -> u32 {
         score
     }
 
-    // detects streaks >= 5 and finder patterns
-    fn line_patterns(matrix: &Matrix<Module>, col: bool) -> u32 {
+    // N1, 3 + (streak - 5) points for every streak >= 5 of the same color
+    fn streaks(matrix: &Matrix<Module>, col: bool) -> u32 {
         let mut score = 0;
-        let (y_mult, x_mult) = match col {
-            true => (matrix.width, 1),
-            false => (1, matrix.width),
-        };
+        for i in 0..matrix.width {
+            let is_on = |j: usize| match col {
+                true => matrix.get(i, j).has(Module::ON),
+                false => matrix.get(j, i).has(Module::ON),
+            };
 
-        let pattern_1 = 0b0000_1011101;
-        let pattern_2 = 0b1011101_0000;
-
-        for y in 0..matrix.width {
-            let mut streak = 1;
-            let mut streak_v = matrix.value[y * y_mult + 0].has(Module::ON);
-
-            let mut window: u16 = streak_v as u16;
-
-            for x in 1..matrix.width {
-                let curr = matrix.value[y * y_mult + x * x_mult].has(Module::ON);
-                if curr == streak_v {
+            let mut streak = 0;
+            let mut streak_v = false;
+            for j in 0..matrix.width {
+                let curr = is_on(j);
+                if j > 0 && curr == streak_v {
                     streak += 1;
                     if streak == 5 {
                         score += 3;
@@ -73,13 +159,32 @@ pub fn score(matrix: &Matrix<Module>) -> u32 {
                     streak = 1;
                     streak_v = curr;
                 }
+            }
+        }
+        score
+    }
+
+    // N3, 40 points for every dark-light-dark-dark-dark-light-dark with 4 light modules before or after it,
+    // the quiet zone is light
+    fn finder_patterns(matrix: &Matrix<Module>, col: bool) -> u32 {
+        let mut score = 0;
+        let width = matrix.width;
+        let pattern = 0b1011101;
 
-                window <<= 1;
-                window |= curr as u16;
-                // 10 = pattern.len() - 1
-                if x >= 10 {
-                    window &= 0b111_1111_1111;
-                    if window == pattern_1 || window == pattern_2 {
+        for i in 0..width {
+            let is_on = |j: usize| match col {
+                true => matrix.get(i, j).has(Module::ON),
+                false => matrix.get(j, i).has(Module::ON),
+            };
+            let light = |start: usize, end: usize| (start..end.min(width)).all(|j| !is_on(j));
+
+            let mut window: u8 = 0;
+            for j in 0..width {
+                let curr = is_on(j);
+                window = (window << 1 | curr as u8) & 0b111_1111;
+                if j >= 6 && window == pattern {
+                    let start = j - 6;
+                    if light(start.saturating_sub(4), start) || light(j + 1, j + 5) {
                         score += 40;
                     }
                 }
@@ -89,8 +194,154 @@ pub fn score(matrix: &Matrix<Module>) -> u32 {
         score
     }
 
-    dark_proportion(matrix)
-        + blocks(matrix)
-        + line_patterns(matrix, true)
-        + line_patterns(matrix, false)
+    Penalty {
+        n1: streaks(matrix, true) + streaks(matrix, false),
+        n2: blocks(matrix),
+        n3: finder_patterns(matrix, true) + finder_patterns(matrix, false),
+        n4: dark_proportion(matrix),
+    }
+}
+
+/// Micro QR only looks at the right and bottom edges (except the timing modules), lower is better.
+/// Same ordering as the spec's 16 * min(dark) + max(dark) with higher being better.
+pub fn score_micro(matrix: &Matrix<Module>) -> u32 {
+    let last = matrix.width - 1;
+    let mut right = 0;
+    let mut bottom = 0;
+    for i in 1..matrix.width {
+        if !matrix.get(last, i).has(Module::ON) {
+            right += 1;
+        }
+        if !matrix.get(i, last).has(Module::ON) {
+            bottom += 1;
+        }
+    }
+    right + bottom + 15 * right.max(bottom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate,
+        qr_code::{Mode, Version, ECL},
+        QrError, QrOptions,
+    };
+
+    #[test]
+    fn qr_options_is_send_and_sync() {
+        fn assert_thread_safe<T: Send + Sync + UnwindSafe + RefUnwindSafe>() {}
+        assert_thread_safe::<QrOptions>();
+        assert_thread_safe::<Box<dyn MaskSelector>>();
+    }
+
+    #[test]
+    fn score_works() {
+        let mut matrix = Matrix::new(Version(1), Module(0));
+        // all light: N1 = 2 * 21 * (3 + 16), N2 = 20 * 20 * 3, N4 = 100
+        assert_eq!(score(&matrix), 2 * 21 * 19 + 1200 + 100);
+
+        // 1:1:3:1:1 with light on both sides counts once, at the edge the quiet zone is light
+        for (x, on) in [1, 0, 1, 1, 1, 0, 1].into_iter().enumerate() {
+            matrix.set(x, 0, Module(on));
+        }
+        // rows: 14 light after the pattern, columns: 5 with 20 light after a dark module
+        let streaks = 12 + 20 * 19 + 5 * 18 + 16 * 19;
+        // only the 13 light pairs after the pattern are still blocks in the top row
+        let blocks = (20 * 19 + 13) * 3;
+        // 5 of 441 modules are dark, 9 full 5% steps from 50%
+        assert_eq!(score(&matrix), streaks + blocks + 40 + 90);
+    }
+
+    #[test]
+    fn mask_selectors_work() {
+        let input = "https://github.com/subygan/qrust";
+        let make = |options: QrOptions| generate(input, &options).unwrap();
+
+        let spec = make(QrOptions::new().mask_selector(Spec));
+        assert_eq!(spec.mask, make(QrOptions::new()).mask);
+        assert_eq!(make(QrOptions::new().mask_selector(Fastest)).mask, Mask::M0);
+
+        for mask in [Mask::M2, Mask::M5] {
+            let target = make(QrOptions::new().mask(Some(mask)));
+            let pixel_weights = target
+                .matrix
+                .value
+                .iter()
+                .map(|m| WeightPixel::new(m.has(Module::ON), 127))
+                .collect();
+            let qr_code = make(QrOptions::new().mask_selector(TargetImage { pixel_weights }));
+            assert_eq!(qr_code.mask, mask);
+        }
+
+        // a fixed mask wins
+        let options = QrOptions::new().mask(Some(Mask::M7)).mask_selector(Fastest);
+        assert_eq!(make(options).mask, Mask::M7);
+
+        // the version picked for the input has to match the target image
+        let pixel_weights = vec![WeightPixel::new(true, 127); 21 * 21];
+        let options = QrOptions::new().mask_selector(TargetImage { pixel_weights });
+        assert_eq!(
+            generate(input, &options).unwrap_err(),
+            QrError::InvalidPixelWeights
+        );
+        assert_eq!(generate("qrust", &options).unwrap().version, Version(1));
+    }
+
+    #[test]
+    fn mask_matches_reference_encoder() {
+        // qrcode doesn't round N4 to 5% steps, which only matters for close scores
+        for (input, version, ecl, ec_level) in [
+            (
+                "https://github.com/subygan/qrust",
+                3,
+                ECL::Low,
+                qrcode::EcLevel::L,
+            ),
+            ("HELLO WORLD", 1, ECL::Quartile, qrcode::EcLevel::Q),
+            ("01234567890123456789", 2, ECL::Medium, qrcode::EcLevel::M),
+            ("mask penalty", 4, ECL::High, qrcode::EcLevel::H),
+            (
+                "the quick brown fox jumps over the lazy dog",
+                7,
+                ECL::Medium,
+                qrcode::EcLevel::M,
+            ),
+        ] {
+            let mode = match version {
+                1 => Mode::Alphanumeric,
+                2 => Mode::Numeric,
+                _ => Mode::Byte,
+            };
+            let qr_code = generate(
+                input,
+                &QrOptions::new()
+                    .min_version(Version(version))
+                    .strict_version(true)
+                    .min_ecl(ecl)
+                    .strict_ecl(true)
+                    .mode(Some(mode)),
+            )
+            .unwrap();
+            let reference = qrcode::QrCode::with_version(
+                input.as_bytes(),
+                qrcode::Version::Normal(version as i16),
+                ec_level,
+            )
+            .unwrap();
+            let colors: Vec<bool> = reference
+                .to_colors()
+                .into_iter()
+                .map(|c| c == qrcode::Color::Dark)
+                .collect();
+
+            let modules: Vec<bool> = qr_code
+                .matrix
+                .value
+                .iter()
+                .map(|m| m.has(Module::ON))
+                .collect();
+            assert_eq!(modules, colors, "{input} V{version}");
+        }
+    }
 }
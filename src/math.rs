@@ -35,3 +35,25 @@ const fn log_table() -> [u8; 256] {
     }
     array
 }
+
+/// Multiplication in GF(256)
+pub fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    ANTILOG_TABLE[(LOG_TABLE[a as usize] as usize + LOG_TABLE[b as usize] as usize) % 255]
+}
+
+/// Division in GF(256), `b` must not be 0
+pub fn div(a: u8, b: u8) -> u8 {
+    assert_ne!(b, 0, "division by 0");
+    if a == 0 {
+        return 0;
+    }
+    ANTILOG_TABLE[(LOG_TABLE[a as usize] as usize + 255 - LOG_TABLE[b as usize] as usize) % 255]
+}
+
+/// 2 raised to any power, negative powers being inverses
+pub fn exp(power: isize) -> u8 {
+    ANTILOG_TABLE[power.rem_euclid(255) as usize]
+}
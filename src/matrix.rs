@@ -1,14 +1,16 @@
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
 
 use crate::{
-    constants::{FORMAT_INFO, VERSION_INFO},
-    qr_code::{Mask, Version, ECL},
+    constants::{FORMAT_INFO, MICRO_FORMAT_INFO, RMQR_FORMAT_INFO, RMQR_SIZES, VERSION_INFO},
+    qr_code::{Mask, Version, ECL, MICRO_MASKS},
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Matrix<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> {
     pub value: Vec<T>,
     pub width: usize,
+    /// Same as `width`, except for rMQR
+    pub height: usize,
 }
 
 impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
@@ -17,8 +19,35 @@ impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
         Matrix {
             value: vec![init; (width) * (width)],
             width,
+            height: width,
         }
     }
+    /// `version` is M1-M4
+    pub fn new_micro(version: Version, init: T) -> Self {
+        let width = version.0 * 2 + 9;
+        Matrix {
+            value: vec![init; (width) * (width)],
+            width,
+            height: width,
+        }
+    }
+    /// `version` is R7x43 = 1 ... R17x139 = 32
+    pub fn new_rmqr(version: Version, init: T) -> Self {
+        let [height, width] = RMQR_SIZES[version.0].map(usize::from);
+        Matrix {
+            value: vec![init; width * height],
+            width,
+            height,
+        }
+    }
+    /// Micro QR is 11-17 modules wide, QR is 21-177
+    pub fn is_micro(&self) -> bool {
+        self.width < 21
+    }
+    /// rMQR is the only non-square symbol
+    pub fn is_rmqr(&self) -> bool {
+        self.width != self.height
+    }
     pub fn get(&self, x: usize, y: usize) -> T {
         self.value[y * self.width + x]
     }
@@ -30,43 +59,174 @@ impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
     }
 
     pub fn set_finder(&mut self) {
-        for (x, mut y) in [(0, 0), (0, self.width - 7), (self.width - 7, 0)] {
-            for i in 0..7 {
-                self.set(x + i, y, (Module::FINDER | Module::ON).into());
+        for (x, y) in [(0, 0), (0, self.width - 7), (self.width - 7, 0)] {
+            self.set_finder_at(x, y);
+        }
+    }
+
+    /// Single finder in the top left, the separator is marked as an off finder module
+    pub fn set_micro_finder(&mut self) {
+        self.set_finder_at(0, 0);
+        for i in 0..8 {
+            self.set(i, 7, Module::FINDER.into());
+            self.set(7, i, Module::FINDER.into());
+        }
+    }
+
+    /// Finder in the top left, finder sub-pattern in the bottom right and corner finders in the other corners.
+    /// The separator is marked as an off finder module, and there's no bottom separator for R7.
+    pub fn set_rmqr_finder(&mut self) {
+        let (width, height) = (self.width, self.height);
+        self.set_finder_at(0, 0);
+        for i in 0..8 {
+            if i < height {
+                self.set(7, i, Module::FINDER.into());
             }
-            y += 1;
+            if height > 7 {
+                self.set(i, 7, Module::FINDER.into());
+            }
+        }
 
-            self.set(x + 0, y, (Module::FINDER | Module::ON).into());
-            for i in 1..6 {
-                self.set(x + i, y, Module::FINDER.into());
+        for y in height - 5..height {
+            for x in width - 5..width {
+                let ring = x.abs_diff(width - 3).max(y.abs_diff(height - 3));
+                self.set(x, y, (Module::FINDER | ((ring != 1) as u8).into()).into());
+            }
+        }
+        self.set(
+            width - 3,
+            height - 3,
+            (Module::FINDER_CENTER | Module::ON).into(),
+        );
+
+        for (x, y, on) in [
+            (width - 1, 0, true),
+            (width - 2, 0, true),
+            (width - 1, 1, true),
+            (width - 2, 1, false),
+        ] {
+            self.set(x, y, (Module::FINDER | (on as u8).into()).into());
+        }
+        // R7 and R9 have the finder or its separator here
+        if height >= 11 {
+            for (x, y, on) in [
+                (0, height - 1, true),
+                (1, height - 1, true),
+                (2, height - 1, true),
+                (0, height - 2, true),
+                (1, height - 2, false),
+            ] {
+                self.set(x, y, (Module::FINDER | (on as u8).into()).into());
+            }
+        } else if height == 9 {
+            for x in 0..3 {
+                self.set(x, height - 1, (Module::FINDER | Module::ON).into());
             }
+        }
+    }
+
+    fn set_finder_at(&mut self, x: usize, mut y: usize) {
+        for i in 0..7 {
+            self.set(x + i, y, (Module::FINDER | Module::ON).into());
+        }
+        y += 1;
+
+        self.set(x, y, (Module::FINDER | Module::ON).into());
+        for i in 1..6 {
+            self.set(x + i, y, Module::FINDER.into());
+        }
+        self.set(x + 6, y, (Module::FINDER | Module::ON).into());
+        y += 1;
+
+        for _ in 0..3 {
+            self.set(x, y, (Module::FINDER | Module::ON).into());
+            self.set(x + 1, y, (Module::FINDER).into());
+            self.set(x + 2, y, (Module::FINDER_CENTER | Module::ON).into());
+            self.set(x + 3, y, (Module::FINDER_CENTER | Module::ON).into());
+            self.set(x + 4, y, (Module::FINDER_CENTER | Module::ON).into());
+            self.set(x + 5, y, (Module::FINDER).into());
             self.set(x + 6, y, (Module::FINDER | Module::ON).into());
             y += 1;
+        }
 
-            for _ in 0..3 {
-                self.set(x + 0, y, (Module::FINDER | Module::ON).into());
-                self.set(x + 1, y, (Module::FINDER).into());
-                self.set(x + 2, y, (Module::FINDER_CENTER | Module::ON).into());
-                self.set(x + 3, y, (Module::FINDER_CENTER | Module::ON).into());
-                self.set(x + 4, y, (Module::FINDER_CENTER | Module::ON).into());
-                self.set(x + 5, y, (Module::FINDER).into());
-                self.set(x + 6, y, (Module::FINDER | Module::ON).into());
-                y += 1;
+        self.set(x, y, (Module::FINDER | Module::ON).into());
+        for i in 1..6 {
+            self.set(x + i, y, (Module::FINDER).into());
+        }
+        self.set(x + 6, y, (Module::FINDER | Module::ON).into());
+        y += 1;
+
+        for i in 0..7 {
+            self.set(x + i, y, (Module::FINDER | Module::ON).into());
+        }
+    }
+
+    /// Alignment patterns on the top and bottom edges, joined by vertical timing patterns
+    pub fn set_rmqr_alignment(&mut self) {
+        let height = self.height;
+        for &center in rmqr_alignment_centers(self.width) {
+            for center_y in [1, height - 2] {
+                for y in center_y - 1..=center_y + 1 {
+                    for x in center - 1..=center + 1 {
+                        let on = (x != center || y != center_y) as u8;
+                        self.set(x, y, (Module::ALIGNMENT | on.into()).into());
+                    }
+                }
             }
+            for y in 3..height - 3 {
+                self.set(
+                    center,
+                    y,
+                    (Module::TIMING | ((y % 2 == 0) as u8).into()).into(),
+                );
+            }
+        }
+    }
 
-            self.set(x + 0, y, (Module::FINDER | Module::ON).into());
-            for i in 1..6 {
-                self.set(x + i, y, (Module::FINDER).into());
+    /// Timing patterns on every edge, this must run after the finders and alignment patterns
+    pub fn set_rmqr_timing(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let mut set_timing = |x: usize, y: usize, on: bool| {
+            if self.get(x, y).into() == Module(0) {
+                self.set(x, y, (Module::TIMING | (on as u8).into()).into());
             }
-            self.set(x + 6, y, (Module::FINDER | Module::ON).into());
-            y += 1;
+        };
+        for x in 0..width {
+            set_timing(x, 0, x % 2 == 0);
+            set_timing(x, height - 1, x % 2 == 0);
+        }
+        for y in 0..height {
+            set_timing(0, y, y % 2 == 0);
+            set_timing(width - 1, y, y % 2 == 0);
+        }
+    }
 
-            for i in 0..7 {
-                self.set(x + i, y, (Module::FINDER | Module::ON).into());
+    /// 18 bit format info next to the finder and the finder sub-pattern, each with its own mask
+    pub fn set_rmqr_format(&mut self, version: Version, ecl: ECL) {
+        let format_info = RMQR_FORMAT_INFO[((ecl == ECL::High) as usize) << 5 | (version.0 - 1)];
+        for (i, mask) in [0b011111101010110010, 0b100000101001111011]
+            .into_iter()
+            .enumerate()
+        {
+            let format_info = format_info ^ mask;
+            for j in 0..18 {
+                let on = ((format_info >> j) as u8 & 1).into();
+                let (x, y) = self.rmqr_format_coords(i == 1, j);
+                self.set(x, y, (Module::FORMAT | on).into());
             }
         }
     }
 
+    /// Coordinates of rMQR format info bit `i`, bit 0 is least significant
+    pub fn rmqr_format_coords(&self, sub_pattern_side: bool, i: usize) -> (usize, usize) {
+        match (sub_pattern_side, i) {
+            (false, i) if i < 15 => (8 + i / 5, 1 + i % 5),
+            (false, i) => (11, i - 14),
+            (true, i) if i < 15 => (self.width - 8 + i / 5, self.height - 6 + i % 5),
+            (true, i) => (self.width - 20 + i, self.height - 6),
+        }
+    }
+
     pub fn set_alignment(&mut self) {
         let version = (self.width - 17) / 4;
         if version == 1 {
@@ -133,38 +293,80 @@ impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
         }
     }
 
+    /// Along the top and left edges
+    pub fn set_micro_timing(&mut self) {
+        for i in 8..self.width {
+            let module = Module::TIMING | ((i as u8 & 1) ^ 1).into();
+            self.set(i, 0, module.into());
+            self.set(0, i, module.into());
+        }
+    }
+
     pub fn set_format(&mut self, ecl: ECL, mask: Mask) {
         let format_info = FORMAT_INFO[ecl as usize][mask as usize];
         for i in 0..15 {
             let on = ((format_info >> i) as u8 & 1).into();
 
-            let y = match i {
-                i if i < 6 => i,
-                6 => 7,
-                _ => 8,
-            };
-            let x = match i {
-                i if i < 8 => 8,
-                8 => 7,
-                _ => 14 - i,
-            };
+            let [(x, y), (x_copy, y_copy)] = self.format_coords(i);
             self.set(x, y, (Module::FORMAT | on).into());
-
-            let y = match i {
-                i if i < 8 => 8,
-                _ => self.width - (15 - i),
-            };
-            let x = match i {
-                i if i < 8 => self.width - (i + 1),
-                _ => 8,
-            };
-            self.set(x, y, (Module::FORMAT_COPY | on).into());
+            self.set(x_copy, y_copy, (Module::FORMAT_COPY | on).into());
         }
 
         // always set bit, not part of format info
         self.set(8, self.width - 8, (Module::FORMAT_COPY | Module::ON).into());
     }
 
+    /// Coordinates of format info bit `i` and its copy, bit 0 is least significant
+    pub fn format_coords(&self, i: usize) -> [(usize, usize); 2] {
+        let y = match i {
+            i if i < 6 => i,
+            6 => 7,
+            _ => 8,
+        };
+        let x = match i {
+            i if i < 8 => 8,
+            8 => 7,
+            _ => 14 - i,
+        };
+
+        let y_copy = match i {
+            i if i < 8 => 8,
+            _ => self.width - (15 - i),
+        };
+        let x_copy = match i {
+            i if i < 8 => self.width - (i + 1),
+            _ => 8,
+        };
+
+        [(x, y), (x_copy, y_copy)]
+    }
+
+    /// `mask` must be one of `MICRO_MASKS`
+    pub fn set_micro_format(&mut self, version: Version, ecl: ECL, mask: Mask) {
+        let symbol = match version.0 {
+            1 => 0,
+            v => v * 2 - 3 + ecl as usize,
+        };
+        let mask = MICRO_MASKS
+            .iter()
+            .position(|&m| m == mask)
+            .expect("Micro QR only has masks M1, M4, M6 and M7");
+        let format_info = MICRO_FORMAT_INFO[symbol][mask];
+        for i in 0..15 {
+            let on = ((format_info >> i) as u8 & 1).into();
+            let (x, y) = self.micro_format_coords(i);
+            self.set(x, y, (Module::FORMAT | on).into());
+        }
+    }
+
+    /// Coordinates of Micro format info bit `i`, bit 0 is least significant
+    pub fn micro_format_coords(&self, i: usize) -> (usize, usize) {
+        match i {
+            i if i < 8 => (8, i + 1),
+            _ => (15 - i, 8),
+        }
+    }
+
     pub fn set_version(&mut self) {
         let version = (self.width - 17) / 4;
         if version < 7 {
@@ -175,16 +377,35 @@ impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
         for i in 0..18 {
             let on = ((info >> i) as u8 & 1).into();
 
-            let x = i / 3;
-            let y = i % 3;
-
-            self.set(x, y + self.width - 11, (Module::VERSION | on).into());
-            self.set(y + self.width - 11, x, (Module::VERSION_COPY | on).into());
+            let [(x, y), (x_copy, y_copy)] = self.version_coords(i);
+            self.set(x, y, (Module::VERSION | on).into());
+            self.set(x_copy, y_copy, (Module::VERSION_COPY | on).into());
         }
     }
 
+    /// Coordinates of version info bit `i` and its copy, bit 0 is least significant
+    pub fn version_coords(&self, i: usize) -> [(usize, usize); 2] {
+        let x = i / 3;
+        let y = i % 3;
+
+        [(x, y + self.width - 11), (y + self.width - 11, x)]
+    }
+
     /// This must run AFTER everything else placed
     pub fn set_data(&mut self, mut get_value: impl FnMut() -> T) {
+        for (x, y) in self.data_coords() {
+            self.set(x, y, get_value());
+        }
+    }
+
+    /// Coordinates of data modules in the order bits are placed.
+    /// Like `set_data`, this must run AFTER everything else placed
+    pub fn data_coords(&self) -> Vec<(usize, usize)> {
+        if self.is_micro() || self.is_rmqr() {
+            return self.micro_data_coords();
+        }
+        let mut coords = Vec::with_capacity(self.value.len());
+
         let mut col = self.width - 1;
         let mut row = self.width - 1;
 
@@ -196,10 +417,10 @@ impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
         loop {
             loop {
                 if self.get(col, row).into() == Module(0) {
-                    self.set(col, row, get_value());
+                    coords.push((col, row));
                 }
                 if self.get(col - 1, row).into() == Module(0) {
-                    self.set(col - 1, row, get_value());
+                    coords.push((col - 1, row));
                 }
                 if row == row_limit {
                     break;
@@ -232,6 +453,32 @@ impl<T: Copy + From<Module> + Into<Module> + BitOrAssign<Module>> Matrix<T> {
                 row_limit = (row_limit as isize + top_bot_gap * row_dir) as usize;
             }
         }
+
+        coords
+    }
+
+    // every function module is marked and the timing belt is col 0, so no edgecases.
+    // rMQR also has a timing belt on the right, so it starts a column earlier.
+    fn micro_data_coords(&self) -> Vec<(usize, usize)> {
+        let mut coords = Vec::with_capacity(self.value.len());
+        let mut upwards = true;
+        let first = if self.is_rmqr() {
+            self.width - 1
+        } else {
+            self.width
+        };
+        for col in (1..first).rev().step_by(2) {
+            for i in 0..self.height {
+                let row = if upwards { self.height - 1 - i } else { i };
+                for x in [col, col - 1] {
+                    if self.get(x, row).into() == Module(0) {
+                        coords.push((x, row));
+                    }
+                }
+            }
+            upwards = !upwards;
+        }
+        coords
     }
 }
 
@@ -317,6 +564,17 @@ impl BitXorAssign for Module {
     }
 }
 
+fn rmqr_alignment_centers(width: usize) -> &'static [usize] {
+    match width {
+        43 => &[21],
+        59 => &[19, 39],
+        77 => &[25, 51],
+        99 => &[23, 49, 75],
+        139 => &[27, 55, 83, 111],
+        _ => &[],
+    }
+}
+
 const ALIGN_OFFSETS: [usize; 34] = [
     16, 18, 20, 22, 24, 26, 28, // 7-13
     20, 22, 24, 24, 26, 28, 28, // 14-20
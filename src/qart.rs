@@ -4,7 +4,7 @@ use crate::{
     data::{BitVec, Data},
     error_correction::remainder,
     matrix::{Matrix, Module},
-    qr_code::{mask_fn, Mask, QrCode},
+    qr_code::{mask_fn, Mask, QrCode, Symbol},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -199,6 +199,7 @@ impl Qart {
             version: self.bit_info.version,
             ecl: self.bit_info.ecl,
             mask: self.bit_info.mask,
+            symbol: Symbol::Normal,
         }
     }
 }
@@ -1,7 +1,7 @@
 use crate::{
     data::Data,
-    error_correction::ecc_and_sequence,
-    mask::score,
+    error_correction::{ecc_and_sequence, micro_ecc_and_sequence, rmqr_ecc_and_sequence},
+    mask::{penalty, score_micro, MaskPenalties, MaskSelector, Penalty, Spec},
     matrix::{Matrix, Module},
 };
 
@@ -14,7 +14,29 @@ pub enum Mode {
     Numeric,
     Alphanumeric,
     Byte,
-    // no plans for Kanji, ECI, StructuredAppend, FNC1,
+    /// Shift JIS double byte characters, requires the `kanji` feature
+    Kanji,
+    // ECI, StructuredAppend and FNC1 are headers written before the segments, see encoding::Header
+}
+
+/// Marks data as formatted to an industry standard
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fnc1 {
+    /// GS1 Application Identifier element strings
+    First,
+    /// Other industry formats, identified by an application indicator:
+    /// 00-99, or an ASCII letter + 100
+    Second(u8),
+}
+
+impl Fnc1 {
+    /// False for a `Second` application indicator outside 00-99 and ASCII letters + 100
+    pub fn is_valid(self) -> bool {
+        match self {
+            Fnc1::First => true,
+            Fnc1::Second(indicator) => indicator < 100 || (indicator - 100).is_ascii_alphabetic(),
+        }
+    }
 }
 
 #[cfg_attr(feature = "wasm", wasm_bindgen)]
@@ -52,13 +74,39 @@ pub enum Mask {
     M7,
 }
 
-#[derive(Debug)]
+pub const MASKS: [Mask; 8] = [
+    Mask::M0,
+    Mask::M1,
+    Mask::M2,
+    Mask::M3,
+    Mask::M4,
+    Mask::M5,
+    Mask::M6,
+    Mask::M7,
+];
+
+/// Micro QR only uses these masks, in format info order
+pub const MICRO_MASKS: [Mask; 4] = [Mask::M1, Mask::M4, Mask::M6, Mask::M7];
+
+/// For Micro QR, `Version` is M1-M4. For rMQR, it's R7x43 = 1 ... R17x139 = 32
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Symbol {
+    Normal,
+    /// Single finder, 11-17 modules wide, L/M/Q ECL only, no ECI/Structured Append/FNC1
+    Micro,
+    /// Rectangular, 7-17 modules high and 27-139 wide, M/H ECL only, always mask M4
+    Rmqr,
+}
+
+#[derive(Clone, Debug)]
 pub struct QrCode {
     pub matrix: Matrix<Module>,
     pub mode: Mode,
     pub version: Version,
     pub ecl: ECL,
     pub mask: Mask,
+    pub symbol: Symbol,
 }
 
 // vec while in rust only land
@@ -66,6 +114,12 @@ pub struct QrCode {
 
 impl QrCode {
     pub fn new(data: Data, mask: Option<Mask>) -> Self {
+        match data.symbol {
+            Symbol::Micro => return QrCode::new_micro(data, mask),
+            Symbol::Rmqr => return QrCode::new_rmqr(data),
+            Symbol::Normal => (),
+        }
+
         let mut qr_code = QrCode {
             matrix: Matrix::new(data.version, Module(0)),
             mode: data.mode,
@@ -76,6 +130,7 @@ impl QrCode {
             } else {
                 Mask::M0
             },
+            symbol: Symbol::Normal,
         };
         qr_code.matrix.set_finder();
         qr_code.matrix.set_alignment();
@@ -93,48 +148,161 @@ impl QrCode {
         });
         qr_code.apply_mask(qr_code.mask);
 
-        if let None = mask {
-            let mut min_score = score(&qr_code.matrix);
+        if mask.is_none() {
+            qr_code.select_mask(&Spec);
+        }
+
+        qr_code
+    }
+
+    /// Same as `new`, with the mask picked by `selector` instead of the penalty score.
+    /// Micro QR and rMQR always use their own mask rules.
+    pub fn new_with_selector(data: Data, selector: &dyn MaskSelector) -> Self {
+        if data.symbol != Symbol::Normal {
+            return QrCode::new(data, None);
+        }
+        if let Some(mask) = selector.fixed_mask() {
+            return QrCode::new(data, Some(mask));
+        }
+        let mut qr_code = QrCode::new(data, Some(Mask::M0));
+        qr_code.select_mask(selector);
+        qr_code
+    }
+
+    fn select_mask(&mut self, selector: &dyn MaskSelector) {
+        let mut min_score = u32::MAX;
+        let mut min_mask = self.mask;
+        for m in MASKS {
+            self.set_mask(m);
+            let score = selector.score(&self.matrix);
+            if score < min_score {
+                min_score = score;
+                min_mask = m;
+            }
+        }
+        self.set_mask(min_mask);
+    }
+
+    /// N1-N4 penalties of every mask, None for Micro QR and rMQR.
+    /// Same for any mask passed to `QrCode::new`, so `Data` can be checked with `QrCode::new(data, Some(Mask::M0))`
+    pub fn mask_penalties(&self) -> Option<MaskPenalties> {
+        if self.symbol != Symbol::Normal {
+            return None;
+        }
+        let mut qr_code = self.clone();
+        Some(qr_code.penalties())
+    }
+
+    fn penalties(&mut self) -> MaskPenalties {
+        let mask = self.mask;
+        let mut penalties = [Penalty::default(); 8];
+        for (i, m) in MASKS.into_iter().enumerate() {
+            self.set_mask(m);
+            penalties[i] = penalty(&self.matrix);
+        }
+        self.set_mask(mask);
+
+        // first mask with the lowest score
+        let best = (0..8).min_by_key(|&i| penalties[i].total()).unwrap();
+        MaskPenalties {
+            penalties,
+            best: MASKS[best],
+        }
+    }
+
+    fn set_mask(&mut self, mask: Mask) {
+        // undo prev mask
+        self.apply_mask(self.mask);
+
+        self.mask = mask;
+        self.apply_mask(self.mask);
+        self.matrix.set_format(self.ecl, self.mask);
+    }
+
+    fn new_micro(data: Data, mask: Option<Mask>) -> Self {
+        let mut qr_code = QrCode {
+            matrix: Matrix::new_micro(data.version, Module(0)),
+            mode: data.mode,
+            version: data.version,
+            ecl: data.ecl,
+            mask: mask.unwrap_or(MICRO_MASKS[0]),
+            symbol: Symbol::Micro,
+        };
+        qr_code.matrix.set_micro_finder();
+        qr_code.matrix.set_micro_timing();
+        qr_code
+            .matrix
+            .set_micro_format(qr_code.version, qr_code.ecl, qr_code.mask);
+
+        let data = micro_ecc_and_sequence(data);
+
+        let mut i = 0;
+        qr_code.matrix.set_data(|| {
+            let val = Module::DATA | ((data[i / 8] >> (7 - (i % 8))) & 1).into();
+            i += 1;
+            val
+        });
+        qr_code.apply_mask(qr_code.mask);
+
+        if mask.is_none() {
+            let mut min_score = score_micro(&qr_code.matrix);
             let mut min_mask = qr_code.mask;
-            for m in [
-                Mask::M1,
-                Mask::M2,
-                Mask::M3,
-                Mask::M4,
-                Mask::M5,
-                Mask::M6,
-                Mask::M7,
-            ] {
+            for m in &MICRO_MASKS[1..] {
                 // undo prev mask
                 qr_code.apply_mask(qr_code.mask);
 
-                qr_code.mask = m;
+                qr_code.mask = *m;
                 qr_code.apply_mask(qr_code.mask);
-                qr_code.matrix.set_format(qr_code.ecl, qr_code.mask);
-                let score = score(&qr_code.matrix);
+                let score = score_micro(&qr_code.matrix);
                 if score < min_score {
                     min_score = score;
                     min_mask = qr_code.mask;
                 }
             }
-            if min_mask != qr_code.mask {
-                // undo prev mask
-                qr_code.apply_mask(qr_code.mask);
-
-                qr_code.mask = min_mask;
-                qr_code.apply_mask(qr_code.mask);
+            // undo prev mask
+            qr_code.apply_mask(qr_code.mask);
 
-                qr_code.matrix.set_format(qr_code.ecl, qr_code.mask);
-            }
+            qr_code.mask = min_mask;
+            qr_code.apply_mask(qr_code.mask);
+            qr_code
+                .matrix
+                .set_micro_format(qr_code.version, qr_code.ecl, qr_code.mask);
         }
 
         qr_code
     }
 
+    fn new_rmqr(data: Data) -> Self {
+        let mut qr_code = QrCode {
+            matrix: Matrix::new_rmqr(data.version, Module(0)),
+            mode: data.mode,
+            version: data.version,
+            ecl: data.ecl,
+            mask: Mask::M4,
+            symbol: Symbol::Rmqr,
+        };
+        qr_code.matrix.set_rmqr_finder();
+        qr_code.matrix.set_rmqr_alignment();
+        qr_code.matrix.set_rmqr_timing();
+        qr_code.matrix.set_rmqr_format(qr_code.version, qr_code.ecl);
+
+        let data = rmqr_ecc_and_sequence(data);
+
+        let mut i = 0;
+        qr_code.matrix.set_data(|| {
+            let val = Module::DATA | ((data[i / 8] >> (7 - (i % 8))) & 1).into();
+            i += 1;
+            val
+        });
+        qr_code.apply_mask(qr_code.mask);
+
+        qr_code
+    }
+
     fn apply_mask(&mut self, mask: Mask) {
         let mask_bit = mask_fn(mask);
 
-        for y in 0..self.matrix.width {
+        for y in 0..self.matrix.height {
             for x in 0..self.matrix.width {
                 let module = self.matrix.get_mut(x, y);
                 if module.has(Module::DATA) {
@@ -157,3 +325,463 @@ pub fn mask_fn(mask: Mask) -> fn(u16, u16) -> bool {
         Mask::M7 => |col: u16, row: u16| ((row + col) % 2 + (row * col) % 3) % 2 == 0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::{
+            MICRO_DATA_BITS, RMQR_CCI_BITS, RMQR_DATA_CODEWORDS, RMQR_EC_CODEWORDS,
+            RMQR_NUM_BLOCKS, RMQR_SIZES,
+        },
+        data::BitVec,
+        generate_micro, generate_rmqr, mask, QrError, QrOptions,
+    };
+
+    /// Modules of `qrcode`'s Micro QR for the same input, with its own segmentation, masking and format info
+    fn micro_reference(input: &str, version: usize, ec_level: qrcode::EcLevel) -> Vec<bool> {
+        let qr_version = qrcode::Version::Micro(version as i16);
+        let mut bits = qrcode::bits::Bits::new(qr_version);
+        bits.push_optimal_data(input.as_bytes()).unwrap();
+        bits.push_terminator(ec_level).unwrap();
+        let (data, ec) =
+            qrcode::ec::construct_codewords(&bits.into_bytes(), qr_version, ec_level).unwrap();
+
+        let mut canvas = qrcode::canvas::Canvas::new(qr_version, ec_level);
+        canvas.draw_all_functional_patterns();
+        if (version, ec_level) == (3, qrcode::EcLevel::L) {
+            // qrcode's draw_data only places 4 bits of the last data codeword for M1 and M3-M, not for M3-L, even
+            // though its DATA_LENGTHS gives M3-L 84 data bits. Its codewords are drawn as one stream instead.
+            let mut stream = BitVec::new();
+            for &codeword in &data[..data.len() - 1] {
+                stream.push_n(codeword.into(), 8);
+            }
+            stream.push_n((data[data.len() - 1] >> 4).into(), 4);
+            for &codeword in &ec {
+                stream.push_n(codeword.into(), 8);
+            }
+            canvas.draw_data(&stream.to_bytes(), &[]);
+        } else {
+            canvas.draw_data(&data, &ec);
+        }
+        canvas
+            .apply_best_mask()
+            .into_colors()
+            .into_iter()
+            .map(|c| c == qrcode::Color::Dark)
+            .collect()
+    }
+
+    #[test]
+    fn micro_matches_reference_encoder() {
+        for (input, version, ecl, ec_level) in [
+            ("01234", 1, ECL::Low, qrcode::EcLevel::L),
+            ("0123456789", 2, ECL::Low, qrcode::EcLevel::L),
+            ("HELLO", 2, ECL::Medium, qrcode::EcLevel::M),
+            ("0123456789", 3, ECL::Low, qrcode::EcLevel::L),
+            ("HELLO WORLD", 3, ECL::Low, qrcode::EcLevel::L),
+            ("hello qr!", 3, ECL::Low, qrcode::EcLevel::L),
+            ("01234567890123456789012", 3, ECL::Low, qrcode::EcLevel::L),
+            ("hello", 3, ECL::Medium, qrcode::EcLevel::M),
+            ("MICRO QR 3", 3, ECL::Medium, qrcode::EcLevel::M),
+            ("012345678901234567", 3, ECL::Medium, qrcode::EcLevel::M),
+            ("HELLO WORLD 123", 4, ECL::Low, qrcode::EcLevel::L),
+            ("micro qr", 4, ECL::Medium, qrcode::EcLevel::M),
+            ("0123456789012", 4, ECL::Quartile, qrcode::EcLevel::Q),
+        ] {
+            let qr_code = generate_micro(
+                input,
+                &QrOptions::new()
+                    .min_version(Version(version))
+                    .strict_version(true)
+                    .min_ecl(ecl)
+                    .strict_ecl(true),
+            )
+            .unwrap();
+            assert_eq!(qr_code.symbol, Symbol::Micro);
+            let modules: Vec<bool> = qr_code
+                .matrix
+                .value
+                .iter()
+                .map(|m| m.has(Module::ON))
+                .collect();
+            assert_eq!(
+                modules,
+                micro_reference(input, version, ec_level),
+                "{input} M{version}"
+            );
+        }
+    }
+
+    #[test]
+    fn micro_empty_input() {
+        let ecls = [ECL::Low, ECL::Medium, ECL::Quartile, ECL::High];
+        for version in 1..=4 {
+            for ecl in ecls {
+                for strict in [false, true] {
+                    let options = QrOptions::new()
+                        .min_version(Version(version))
+                        .strict_version(strict)
+                        .min_ecl(ecl)
+                        .strict_ecl(strict);
+                    match generate_micro("", &options) {
+                        Ok(qr_code) => {
+                            let (v, e) = (qr_code.version.0, qr_code.ecl as usize);
+                            assert!(v >= version && e >= ecl as usize);
+                            assert!(MICRO_DATA_BITS[v][e] > 0);
+                        }
+                        // M4 has every ECL but High
+                        Err(err) => {
+                            assert_eq!(err, QrError::ExceedsMaxCapacity);
+                            assert!(strict || ecl == ECL::High);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn micro_capacity() {
+        let options = QrOptions::new();
+        assert_eq!(
+            generate_micro("12345", &options).unwrap().version,
+            Version(1)
+        );
+        assert_eq!(
+            generate_micro("123456", &options).unwrap().version,
+            Version(2)
+        );
+        assert_eq!(
+            generate_micro("ABCDEF", &options).unwrap().version,
+            Version(2)
+        );
+        assert_eq!(generate_micro("abc", &options).unwrap().version, Version(3));
+        assert_eq!(
+            generate_micro(&"1".repeat(35), &options).unwrap().version,
+            Version(4)
+        );
+        assert_eq!(
+            generate_micro(&"1".repeat(36), &options).unwrap_err(),
+            QrError::ExceedsMaxCapacity
+        );
+        assert_eq!(
+            generate_micro("1", &options.min_ecl(ECL::High)).unwrap_err(),
+            QrError::ExceedsMaxCapacity
+        );
+        let options = QrOptions::new().mode(Some(Mode::Numeric));
+        assert_eq!(
+            generate_micro("12A", &options).unwrap_err(),
+            QrError::InvalidEncoding
+        );
+        for mask in [Mask::M0, Mask::M2, Mask::M3, Mask::M5] {
+            assert_eq!(
+                generate_micro("1", &QrOptions::new().mask(Some(mask))).unwrap_err(),
+                QrError::InvalidMask
+            );
+        }
+        let qr_code = generate_micro("1", &QrOptions::new().mask(Some(Mask::M6))).unwrap();
+        assert_eq!(qr_code.mask, Mask::M6);
+
+        // Micro QR and rMQR don't take mask selectors
+        let options = QrOptions::new().mask_selector(mask::Fastest);
+        assert_eq!(
+            generate_micro("1", &options).unwrap_err(),
+            QrError::InvalidMask
+        );
+        assert_eq!(
+            generate_rmqr("1", &options).unwrap_err(),
+            QrError::InvalidMask
+        );
+    }
+
+    #[test]
+    fn rmqr_data_modules_match_codewords() {
+        for version in 1..=32 {
+            let mut matrix = Matrix::new_rmqr(Version(version), Module(0));
+            matrix.set_rmqr_finder();
+            matrix.set_rmqr_alignment();
+            matrix.set_rmqr_timing();
+            matrix.set_rmqr_format(Version(version), ECL::Medium);
+
+            let codewords = RMQR_DATA_CODEWORDS[version][ECL::Medium as usize]
+                + RMQR_EC_CODEWORDS[version][ECL::Medium as usize];
+            let remainder_bits = matrix.data_coords().len() - codewords as usize * 8;
+            assert!(remainder_bits < 8, "R{}x{}", matrix.height, matrix.width);
+        }
+    }
+
+    /// Multiplication in GF(256) with the QR code polynomial, kept separate from `math` for the reader below
+    fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0;
+        while b != 0 {
+            if b & 1 == 1 {
+                product ^= a;
+            }
+            a = (a << 1) ^ if a & 0x80 != 0 { 0x1d } else { 0 };
+            b >>= 1;
+        }
+        product
+    }
+
+    /// Reads an rMQR symbol back as ISO/IEC 23941 describes it, without the crate's module flags, data coordinates or
+    /// error correction: format information with both masks and its BCH(18, 6) code, mask 000, two module wide columns
+    /// from the bottom right, block de-interleaving, Reed-Solomon syndromes and the segments. Returns the version, ECL
+    /// and text.
+    fn read_rmqr(qr_code: &QrCode) -> (usize, ECL, String) {
+        let matrix = &qr_code.matrix;
+        let (w, h) = (matrix.width, matrix.height);
+        let on = |x: usize, y: usize| matrix.get(x, y).has(Module::ON);
+
+        // most significant bit first
+        let mut finder_side = 0;
+        for y in (1..=3).rev() {
+            finder_side = finder_side << 1 | on(11, y) as u32;
+        }
+        for x in (8..=10).rev() {
+            for y in (1..=5).rev() {
+                finder_side = finder_side << 1 | on(x, y) as u32;
+            }
+        }
+        let mut sub_pattern_side = 0;
+        for x in 3..=5 {
+            sub_pattern_side = sub_pattern_side << 1 | on(w - x, h - 6) as u32;
+        }
+        for x in 6..=8 {
+            for y in 2..=6 {
+                sub_pattern_side = sub_pattern_side << 1 | on(w - x, h - y) as u32;
+            }
+        }
+        let format = finder_side ^ 0x1fab2;
+        assert_eq!(format, sub_pattern_side ^ 0x20a7b);
+        // generator x^12 + x^11 + x^10 + x^9 + x^8 + x^5 + x^2 + 1
+        let mut remainder = format >> 12 << 12;
+        for bit in (12..18).rev() {
+            if remainder >> bit & 1 == 1 {
+                remainder ^= 0x1f25 << (bit - 12);
+            }
+        }
+        assert_eq!(format, format >> 12 << 12 | remainder);
+        let version = (format >> 12 & 0b11111) as usize + 1;
+        let ecl = match format >> 17 {
+            0 => ECL::Medium,
+            _ => ECL::High,
+        };
+        assert_eq!(RMQR_SIZES[version].map(usize::from), [h, w]);
+
+        let alignment: &[usize] = match w {
+            43 => &[21],
+            59 => &[19, 39],
+            77 => &[25, 51],
+            99 => &[23, 49, 75],
+            139 => &[27, 55, 83, 111],
+            _ => &[],
+        };
+        let is_function = |x: usize, y: usize| {
+            // timing and corner finder patterns on the edges, finder and separator
+            x == 0 || y == 0 || x == w - 1 || y == h - 1 || (x < 8 && y < 8)
+                // format information
+                || ((8..=10).contains(&x) && (1..=5).contains(&y))
+                || (x == 11 && (1..=3).contains(&y))
+                || ((w - 8..=w - 6).contains(&x) && (h - 6..=h - 2).contains(&y))
+                || ((w - 5..=w - 3).contains(&x) && y == h - 6)
+                // finder sub-pattern and the inner corner finder modules
+                || (x >= w - 5 && y >= h - 5)
+                || (x, y) == (w - 2, 1)
+                || (x, y) == (1, h - 2)
+                // alignment patterns and their timing columns
+                || alignment
+                    .iter()
+                    .any(|&c| x == c || (x.abs_diff(c) <= 1 && (y <= 2 || y >= h - 3)))
+        };
+
+        let mut bits = Vec::new();
+        let mut upwards = true;
+        for x in (1..w - 1).rev().step_by(2) {
+            for i in 0..h {
+                let y = if upwards { h - 1 - i } else { i };
+                for x in [x, x - 1] {
+                    if !is_function(x, y) {
+                        bits.push(on(x, y) ^ ((y / 2 + x / 3) % 2 == 0));
+                    }
+                }
+            }
+            upwards = !upwards;
+        }
+
+        let num_data = RMQR_DATA_CODEWORDS[version][ecl as usize] as usize;
+        let num_ec = RMQR_EC_CODEWORDS[version][ecl as usize] as usize;
+        assert!(bits.len() - (num_data + num_ec) * 8 < 8);
+        let mut codewords = bits
+            .chunks(8)
+            .take(num_data + num_ec)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | bit as u8));
+
+        // shorter blocks first, data codewords interleaved, then error correction
+        let blocks = RMQR_NUM_BLOCKS[version][ecl as usize] as usize;
+        let short = num_data / blocks;
+        let mut data_blocks = vec![Vec::new(); blocks];
+        for i in 0..=short {
+            for (b, block) in data_blocks.iter_mut().enumerate() {
+                if i < short || b >= blocks - num_data % blocks {
+                    block.push(codewords.next().unwrap());
+                }
+            }
+        }
+        let mut full_blocks = data_blocks.clone();
+        for _ in 0..num_ec / blocks {
+            for block in full_blocks.iter_mut() {
+                block.push(codewords.next().unwrap());
+            }
+        }
+        for block in &full_blocks {
+            let mut x = 1;
+            for _ in 0..num_ec / blocks {
+                let syndrome = block.iter().fold(0, |acc, &c| gf_mul(acc, x) ^ c);
+                assert_eq!(syndrome, 0, "R{h}x{w}");
+                x = gf_mul(x, 2);
+            }
+        }
+
+        let data: Vec<bool> = data_blocks
+            .concat()
+            .into_iter()
+            .flat_map(|c| (0..8).rev().map(move |i| c >> i & 1 == 1))
+            .collect();
+        let mut bits = data.iter();
+        let read = |bits: &mut std::slice::Iter<bool>, n: usize| {
+            (0..n).fold(0, |acc, _| acc << 1 | *bits.next().unwrap() as usize)
+        };
+        const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+        let mut text = Vec::new();
+        // the terminator may be cut short at the end
+        while bits.len() >= 3 {
+            let mode = read(&mut bits, 3);
+            if mode == 0 {
+                break;
+            }
+            let mut count = read(&mut bits, RMQR_CCI_BITS[version][mode - 1] as usize);
+            match mode {
+                1 => {
+                    while count > 0 {
+                        let digits = count.min(3);
+                        let value = read(&mut bits, [4, 7, 10][digits - 1]);
+                        text.extend(format!("{value:0digits$}").bytes());
+                        count -= digits;
+                    }
+                }
+                2 => {
+                    while count > 0 {
+                        let chars = count.min(2);
+                        let value = read(&mut bits, [6, 11][chars - 1]);
+                        if chars == 2 {
+                            text.push(ALPHANUMERIC[value / 45]);
+                        }
+                        text.push(ALPHANUMERIC[value % 45]);
+                        count -= chars;
+                    }
+                }
+                3 => text.extend((0..count).map(|_| read(&mut bits, 8) as u8)),
+                _ => panic!("unexpected mode {mode}"),
+            }
+        }
+        (version, ecl, String::from_utf8(text).unwrap())
+    }
+
+    #[test]
+    fn rmqr_reads_back() {
+        for (input, version, ecl) in [
+            ("123456", 1, ECL::Medium),
+            ("1234", 1, ECL::High),
+            ("RMQR", 17, ECL::Medium),
+            ("hello rmqr", 17, ECL::Medium),
+            ("ORDER 12345678901234 ref=abc", 22, ECL::Medium),
+            ("ORDER 12345678901234 ref=abc", 22, ECL::High),
+            ("0042 ünïcödé HTTPS://EXAMPLE.COM", 16, ECL::Medium),
+            (
+                &"https://github.com/subygan/qrust ".repeat(2),
+                32,
+                ECL::High,
+            ),
+        ] {
+            let options = QrOptions::new()
+                .min_version(Version(version))
+                .strict_version(true)
+                .min_ecl(ecl)
+                .strict_ecl(true);
+            let qr_code = generate_rmqr(input, &options).unwrap();
+            assert_eq!(read_rmqr(&qr_code), (version, ecl, input.to_string()));
+        }
+    }
+
+    #[test]
+    fn rmqr_layout() {
+        let options = QrOptions::new()
+            .min_version(Version(11))
+            .strict_version(true);
+        let qr_code = generate_rmqr("RMQR", &options).unwrap();
+        let matrix = &qr_code.matrix;
+        assert_eq!((matrix.width, matrix.height), (27, 11));
+        assert_eq!(qr_code.symbol, Symbol::Rmqr);
+        assert_eq!(qr_code.mask, Mask::M4);
+
+        let on = |x, y| matrix.get(x, y).has(Module::ON);
+        // finder sub-pattern
+        for (x, y) in [(22, 6), (26, 10), (24, 8)] {
+            assert!(on(x, y) && matrix.get(x, y).has(Module::FINDER));
+        }
+        assert!(!on(23, 7) && !on(25, 9));
+        // corner finders
+        assert!(on(26, 0) && on(25, 0) && on(26, 1) && !on(25, 1));
+        assert!(on(0, 10) && on(1, 10) && on(2, 10) && on(0, 9) && !on(1, 9));
+        // timing
+        assert!(on(12, 0) && !on(13, 0) && on(12, 10) && !on(13, 10));
+        assert!(matrix.get(13, 0).has(Module::TIMING));
+    }
+
+    #[test]
+    fn rmqr_capacity() {
+        let options = QrOptions::new().min_version(Version(1));
+        let qr_code = generate_rmqr(&"1".repeat(12), &options).unwrap();
+        assert_eq!((qr_code.version, qr_code.ecl), (Version(1), ECL::Medium));
+        let qr_code = generate_rmqr(&"1".repeat(5), &options).unwrap();
+        assert_eq!((qr_code.version, qr_code.ecl), (Version(1), ECL::High));
+        // keeps the height
+        let qr_code = generate_rmqr(&"1".repeat(13), &options).unwrap();
+        assert_eq!(qr_code.version, Version(2));
+        assert_eq!(
+            generate_rmqr(&"1".repeat(103), &options).unwrap_err(),
+            QrError::ExceedsMaxCapacity
+        );
+
+        let options = QrOptions::new().min_version(Version(32));
+        assert!(generate_rmqr(&"1".repeat(361), &options).is_ok());
+        assert!(generate_rmqr(&"a".repeat(150), &options).is_ok());
+        assert_eq!(
+            generate_rmqr(&"1".repeat(362), &options).unwrap_err(),
+            QrError::ExceedsMaxCapacity
+        );
+    }
+
+    #[test]
+    fn mask_penalties_work() {
+        let input = "https://github.com/subygan/qrust";
+        let auto = crate::generate(input, &QrOptions::new()).unwrap();
+        let manual = crate::generate(input, &QrOptions::new().mask(Some(Mask::M3))).unwrap();
+
+        let penalties = auto.mask_penalties().unwrap();
+        assert_eq!(penalties.best, auto.mask);
+        assert_eq!(manual.mask_penalties(), Some(penalties));
+
+        let best = penalties.penalties[auto.mask as usize].total();
+        assert!(penalties.penalties.iter().all(|p| p.total() >= best));
+        assert_eq!(best, crate::mask::score(&auto.matrix));
+        assert_eq!(
+            penalties.penalties[Mask::M3 as usize].total(),
+            crate::mask::score(&manual.matrix)
+        );
+
+        let micro = generate_micro("1", &QrOptions::new()).unwrap();
+        assert_eq!(micro.mask_penalties(), None);
+    }
+}
@@ -1,29 +1,295 @@
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "png")]
+pub mod png;
+#[cfg(feature = "raster")]
+pub mod raster;
 #[cfg(feature = "svg")]
 pub mod svg;
 #[cfg(feature = "text")]
 pub mod text;
 
-#[cfg(feature = "svg")]
-use crate::qr_code::QrCode;
+#[cfg(feature = "render")]
+use std::ops::{BitAnd, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 
-#[cfg(feature = "svg")]
+#[cfg(any(feature = "png", feature = "pdf", feature = "text"))]
+use crate::RenderError;
+#[cfg(feature = "render")]
+use crate::{
+    bit_info::{BitInfo, Info},
+    constants::{NUM_BLOCKS, NUM_EC_CODEWORDS},
+    matrix::Module,
+    qr_code::{QrCode, Symbol, Version, ECL},
+    ContrastError, LogoError, SizeError,
+};
+
+/// In `Role` order
+#[cfg(any(feature = "png", feature = "pdf", feature = "text"))]
+const ROLES: [Role; 7] = [
+    Role::Data,
+    Role::Finder,
+    Role::FinderCenter,
+    Role::Alignment,
+    Role::Timing,
+    Role::Format,
+    Role::Version,
+];
+
+#[cfg(feature = "render")]
+#[derive(Clone)]
 pub struct RenderData<'m> {
     qr_code: &'m QrCode,
     foreground: String,
     background: String,
     unit: usize,
     margin: usize,
-    toggle_options: u8,
+    shape: Shape,
+    finder_shape: FinderShape,
+    module_size: f64,
+    role_colors: [Option<String>; 7],
+    role_shapes: [Option<Shape>; 7],
+    path_mode: PathMode,
+    logo: Option<Logo>,
+    foreground_fill: Option<Fill>,
+    background_fill: Option<Fill>,
+    physical_size: Option<PhysicalSize>,
+    dpi: Option<f64>,
+    toggle_options: Toggle,
 }
 
-#[cfg(feature = "svg")]
-pub enum Toggle {
-    Background,
-    BackgroundPixels,
-    ForegroundPixels,
+/// Which layers the SVG and PDF renderers draw, `Background` and `ForegroundPixels` by default
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(transparent)]
+pub struct Toggle(u8);
+
+#[cfg(feature = "render")]
+impl Toggle {
+    // bit flags
+    /// A rectangle of `background` behind the whole code
+    pub const BACKGROUND: Toggle = Toggle(1 << 0);
+    /// Light modules drawn in `background`
+    pub const BACKGROUND_PIXELS: Toggle = Toggle(1 << 1);
+    /// Dark modules drawn in `foreground` and role colors
+    pub const FOREGROUND_PIXELS: Toggle = Toggle(1 << 2);
+
+    /// Every layer
+    pub const fn all() -> Self {
+        Toggle(0b111)
+    }
+    /// No layer
+    pub const fn empty() -> Self {
+        Toggle(0)
+    }
+    /// Returns true if self contains all flags set in `flags`
+    pub fn has(self, flags: Toggle) -> bool {
+        (self & flags) == flags
+    }
+    pub fn set(&mut self, flags: Toggle) {
+        *self |= flags;
+    }
 }
 
-#[cfg(feature = "svg")]
+#[cfg(feature = "render")]
+impl Default for Toggle {
+    fn default() -> Self {
+        Toggle::BACKGROUND | Toggle::FOREGROUND_PIXELS
+    }
+}
+
+#[cfg(feature = "render")]
+impl BitAnd for Toggle {
+    type Output = Toggle;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Toggle(self.0 & rhs.0)
+    }
+}
+
+#[cfg(feature = "render")]
+impl BitOr for Toggle {
+    type Output = Toggle;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Toggle(self.0 | rhs.0)
+    }
+}
+
+#[cfg(feature = "render")]
+impl BitOrAssign for Toggle {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[cfg(feature = "render")]
+impl BitXor for Toggle {
+    type Output = Toggle;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Toggle(self.0 ^ rhs.0)
+    }
+}
+
+#[cfg(feature = "render")]
+impl BitXorAssign for Toggle {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
+}
+
+#[cfg(feature = "render")]
+impl Not for Toggle {
+    type Output = Toggle;
+
+    /// Only flips the defined flags
+    fn not(self) -> Self::Output {
+        Toggle(!self.0 & Toggle::all().0)
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shape {
+    Square,
+    Circle,
+    RoundedSquare,
+    Diamond,
+    /// Rounded squares with square corners wherever a neighbour of the same color touches
+    Liquid,
+}
+
+/// Finder patterns are drawn as a single ring and center ("eye") unless `Modules`
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FinderShape {
+    /// Same `Shape` and `module_size` as every other module
+    Modules,
+    Square,
+    Rounded,
+    Circle,
+}
+
+/// How `render_svg` writes full size `Shape::Square` modules, other shapes are always drawn one by one
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathMode {
+    /// One subpath per module
+    Modules,
+    /// Outlines of connected modules of the same color, in modules with a `viewBox` scaled to `width` x `height`
+    Merged,
+    /// Same as `Merged`, in pixels (multiples of `unit`) and a `viewBox` of the pixel size
+    MergedPixels,
+}
+
+/// Smallest module that prints reliably, in millimetres
+#[cfg(feature = "render")]
+pub const MIN_MODULE_MM: f64 = 0.25;
+
+/// In millimetres
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PhysicalSize {
+    /// Total width, including the margin. The margin grows by whole modules to fill it as closely as possible.
+    Width(f64),
+    /// Size of a module
+    ModuleSize(f64),
+}
+
+/// SVG paint server, positions are relative to the whole image (0.0-1.0)
+#[cfg(feature = "render")]
+#[derive(Clone, Debug)]
+pub enum Fill {
+    /// `stops` are (offset, color)
+    LinearGradient {
+        from: (f64, f64),
+        to: (f64, f64),
+        stops: Vec<(f64, String)>,
+    },
+    /// From the center to the middle of the edges, `stops` are (offset, color)
+    RadialGradient { stops: Vec<(f64, String)> },
+    /// `href` tiled every `width` x `height` modules
+    Image {
+        href: String,
+        width: usize,
+        height: usize,
+    },
+}
+
+/// Image centered over the symbol, the data modules beneath it aren't drawn
+#[cfg(feature = "render")]
+#[derive(Clone, Debug)]
+pub struct Logo {
+    /// `href` of the SVG `<image>`, e.g. a data URI
+    pub href: String,
+    /// In modules
+    pub width: usize,
+    /// In modules
+    pub height: usize,
+}
+
+/// What a module is part of, from its `Module` flags
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    /// Data, error correction and remainder bits
+    Data,
+    /// Finder pattern ring and rMQR corner finders
+    Finder,
+    FinderCenter,
+    Alignment,
+    Timing,
+    /// Format information, including the dark module
+    Format,
+    Version,
+}
+
+#[cfg(feature = "render")]
+impl Role {
+    pub fn of(module: Module) -> Role {
+        if module.has(Module::FINDER_CENTER) {
+            Role::FinderCenter
+        } else if module.has(Module::FINDER) {
+            Role::Finder
+        } else if module.has(Module::ALIGNMENT) {
+            Role::Alignment
+        } else if module.has(Module::TIMING) {
+            Role::Timing
+        } else if module.has(Module::FORMAT) {
+            Role::Format
+        } else if module.has(Module::VERSION) {
+            Role::Version
+        } else {
+            Role::Data
+        }
+    }
+}
+
+/// A module of the rendered code including the margin, see `RenderData::modules`
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlacedModule {
+    /// In modules from the top left corner of the margin
+    pub x: usize,
+    pub y: usize,
+    /// `None` in the margin
+    pub role: Option<Role>,
+    pub dark: bool,
+    /// Under the logo, see `RenderData::logo`
+    pub hidden: bool,
+}
+
+/// An output format for `RenderData::render`. Each built-in renderer wraps its `render_*` function, a custom backend,
+/// e.g. a canvas, can draw `RenderData::modules` with `RenderData::color`.
+/// Renderers that parse colors return `RenderError::InvalidColor` for ones they can't, they never fall back to others.
+#[cfg(feature = "render")]
+pub trait Renderer {
+    type Output;
+
+    fn render(&self, render: &RenderData) -> Self::Output;
+}
+
+#[cfg(feature = "render")]
 impl<'m> RenderData<'m> {
     pub fn new(qr_code: &'m QrCode) -> Self {
         RenderData {
@@ -32,22 +298,263 @@ impl<'m> RenderData<'m> {
             background: "#fff".into(),
             unit: 1,
             margin: 2,
-            toggle_options: 0,
+            shape: Shape::Square,
+            finder_shape: FinderShape::Modules,
+            module_size: 1.0,
+            role_colors: Default::default(),
+            role_shapes: [None; 7],
+            path_mode: PathMode::Modules,
+            logo: None,
+            foreground_fill: None,
+            background_fill: None,
+            physical_size: None,
+            dpi: None,
+            toggle_options: Toggle::default(),
         }
-        .toggle(Toggle::Background)
-        .toggle(Toggle::ForegroundPixels)
     }
     pub fn width(&self) -> usize {
-        (self.qr_code.matrix.width + self.margin * 2) * self.unit
+        let (unit, margin) = self.layout();
+        (self.qr_code.matrix.width + margin * 2) * unit
+    }
+    /// Same as `width`, except for rMQR
+    pub fn height(&self) -> usize {
+        let (unit, margin) = self.layout();
+        (self.qr_code.matrix.height + margin * 2) * unit
+    }
+    /// Width and height in modules, including the margin
+    pub fn grid_size(&self) -> (usize, usize) {
+        let (_, margin) = self.layout();
+        let matrix = &self.qr_code.matrix;
+        (matrix.width + margin * 2, matrix.height + margin * 2)
     }
+    /// `x` and `y` include the margin, anything outside the symbol is a light margin module
+    pub fn module_at(&self, x: usize, y: usize) -> PlacedModule {
+        let matrix = &self.qr_code.matrix;
+        let mut placed = PlacedModule {
+            x,
+            y,
+            role: None,
+            dark: false,
+            hidden: false,
+        };
+        let (_, margin) = self.layout();
+        if let (Some(mx), Some(my)) = (x.checked_sub(margin), y.checked_sub(margin)) {
+            if mx < matrix.width && my < matrix.height {
+                let module = matrix.get(mx, my);
+                placed.role = Some(Role::of(module));
+                placed.dark = module.has(Module::ON);
+                placed.hidden = self.hides(mx, my);
+            }
+        }
+        placed
+    }
+    /// Every module of `grid_size` row by row, the margin included
+    pub fn modules(&self) -> impl Iterator<Item = PlacedModule> + '_ {
+        let (width, height) = self.grid_size();
+        (0..height).flat_map(move |y| (0..width).map(move |x| self.module_at(x, y)))
+    }
+    /// Role color of dark modules, otherwise `background`. Fills are ignored.
+    pub fn color(&self, module: &PlacedModule) -> &str {
+        match module.role {
+            Some(role) if module.dark && !module.hidden => self.color_of(role),
+            _ => &self.background,
+        }
+    }
+    pub fn render<R: Renderer>(&self, renderer: R) -> R::Output {
+        renderer.render(self)
+    }
+    /// Pixels per module, ignored once `physical_size` is set
     pub fn unit(mut self, unit: usize) -> Self {
         self.unit = unit;
         self
     }
+    /// Quiet zone in modules, `PhysicalSize::Width` may add to it
     pub fn margin(mut self, margin: usize) -> Self {
         self.margin = margin;
         self
     }
+    pub fn shape(mut self, shape: Shape) -> Self {
+        self.shape = shape;
+        self
+    }
+    pub fn finder_shape(mut self, finder_shape: FinderShape) -> Self {
+        self.finder_shape = finder_shape;
+        self
+    }
+    /// Size of a module relative to `unit`, clamped to 0.0-1.0. Modules stay centered in their unit.
+    pub fn module_size(mut self, module_size: f64) -> Self {
+        self.module_size = module_size.clamp(0.0, 1.0);
+        self
+    }
+    /// Foreground color of the dark modules of `role`, instead of `foreground`
+    pub fn role_color(mut self, role: Role, color: String) -> Self {
+        self.role_colors[role as usize] = Some(color);
+        self
+    }
+    /// `Shape` of the modules of `role`, instead of `shape`
+    pub fn role_shape(mut self, role: Role, shape: Shape) -> Self {
+        self.role_shapes[role as usize] = Some(shape);
+        self
+    }
+    pub fn path_mode(mut self, path_mode: PathMode) -> Self {
+        self.path_mode = path_mode;
+        self
+    }
+    /// Replaces `unit` with whole pixels per module at `dpi`, rounded down for `PhysicalSize::Width` so the image
+    /// never gets wider. `render_svg` then sets its size in millimetres and PNGs record the DPI.
+    /// The size is resolved from the final `margin` whenever it's needed, so the builder order doesn't matter.
+    /// Modules below `MIN_MODULE_MM` are allowed, check `is_below_printable_minimum` to warn about them.
+    pub fn physical_size(mut self, size: PhysicalSize, dpi: f64) -> Result<Self, SizeError> {
+        let (PhysicalSize::Width(mm) | PhysicalSize::ModuleSize(mm)) = size;
+        if !(mm > 0.0 && mm.is_finite() && dpi > 0.0 && dpi.is_finite()) {
+            return Err(SizeError::InvalidSize);
+        }
+        self.physical_size = Some(size);
+        self.dpi = Some(dpi);
+        match self.pixels_per_module() {
+            0 => Err(SizeError::InvalidSize),
+            _ => Ok(self),
+        }
+    }
+    /// Physical module size in millimetres, if `physical_size` was set
+    pub fn module_mm(&self) -> Option<f64> {
+        let (unit, _) = self.layout();
+        self.dpi.map(|dpi| unit as f64 / dpi * 25.4)
+    }
+    /// Whole pixels per module for `physical_size`, 0 if it's too small for one
+    fn pixels_per_module(&self) -> usize {
+        let (Some(size), Some(dpi)) = (self.physical_size, self.dpi) else {
+            return self.unit;
+        };
+        let (PhysicalSize::Width(mm) | PhysicalSize::ModuleSize(mm)) = size;
+        let pixels = mm / 25.4 * dpi;
+        match size {
+            // rMQR is wider than high, the width is what's given
+            PhysicalSize::Width(_) => {
+                let modules = self.qr_code.matrix.width + self.margin * 2;
+                (pixels / modules as f64).floor() as usize
+            }
+            PhysicalSize::ModuleSize(_) => pixels.round() as usize,
+        }
+    }
+    /// `unit` and `margin` as rendered, `PhysicalSize::Width` widens the margin by whole modules to fill the width.
+    /// A `margin` set too large for the width after `physical_size` still gets 1 pixel per module.
+    fn layout(&self) -> (usize, usize) {
+        let unit = match self.physical_size {
+            Some(_) => self.pixels_per_module().max(1),
+            None => self.unit,
+        };
+        match (self.physical_size, self.dpi) {
+            (Some(PhysicalSize::Width(mm)), Some(dpi)) => {
+                let modules = self.qr_code.matrix.width + self.margin * 2;
+                let fit = (mm / 25.4 * dpi / unit as f64).floor() as usize;
+                (unit, self.margin + fit.saturating_sub(modules) / 2)
+            }
+            _ => (unit, self.margin),
+        }
+    }
+    /// Copy with `unit` and `margin` resolved by `layout`, for renderers that read them directly
+    #[cfg(any(feature = "text", feature = "svg", feature = "png", test))]
+    fn resolved(&self) -> Self {
+        let (unit, margin) = self.layout();
+        RenderData {
+            unit,
+            margin,
+            physical_size: None,
+            ..self.clone()
+        }
+    }
+    /// Whether `module_mm` is smaller than `MIN_MODULE_MM`, too small to print reliably
+    pub fn is_below_printable_minimum(&self) -> bool {
+        self.module_mm().is_some_and(|mm| mm < MIN_MODULE_MM)
+    }
+    /// Reserves a centered area for `logo`. Function patterns in the area are still drawn on top of it.
+    /// Every codeword with a module in the area counts as an error, and each block must be able to correct them.
+    pub fn logo(mut self, logo: Logo) -> Result<Self, LogoError> {
+        let qr_code = self.qr_code;
+        if qr_code.symbol != Symbol::Normal {
+            return Err(LogoError::UnsupportedSymbol);
+        }
+        let matrix = &qr_code.matrix;
+        if logo.width == 0
+            || logo.height == 0
+            || logo.width > matrix.width
+            || logo.height > matrix.height
+        {
+            return Err(LogoError::InvalidSize);
+        }
+
+        let bit_info = BitInfo::new(qr_code.mode, qr_code.version, qr_code.ecl, qr_code.mask);
+        let blocks = NUM_BLOCKS[qr_code.version.0][qr_code.ecl as usize] as usize;
+        let mut hidden = vec![Vec::new(); blocks];
+        self.logo = Some(logo);
+        for y in 0..matrix.height {
+            for x in 0..matrix.width {
+                let info = bit_info.matrix.get(x, y);
+                if self.hides(x, y)
+                    && info.module.has(Module::DATA)
+                    && info.module != Info::REMAINDER
+                {
+                    hidden[info.block as usize].push(info.bit / 8);
+                }
+            }
+        }
+
+        let ec_codewords = NUM_EC_CODEWORDS[qr_code.version.0][qr_code.ecl as usize] as usize;
+        let correctable =
+            (ec_codewords / blocks - misdecode_protection(qr_code.version, qr_code.ecl)) / 2;
+        for mut codewords in hidden {
+            codewords.sort_unstable();
+            codewords.dedup();
+            if codewords.len() > correctable {
+                return Err(LogoError::ExceedsErrorCorrection);
+            }
+        }
+        Ok(self)
+    }
+    /// Top left of the logo area in modules
+    fn logo_area(&self) -> Option<(usize, usize, &Logo)> {
+        let logo = self.logo.as_ref()?;
+        let matrix = &self.qr_code.matrix;
+        Some((
+            (matrix.width - logo.width) / 2,
+            (matrix.height - logo.height) / 2,
+            logo,
+        ))
+    }
+    /// True for data modules under the logo
+    fn hides(&self, x: usize, y: usize) -> bool {
+        self.logo_area().is_some_and(|(left, top, logo)| {
+            (left..left + logo.width).contains(&x)
+                && (top..top + logo.height).contains(&y)
+                && Role::of(self.qr_code.matrix.get(x, y)) == Role::Data
+        })
+    }
+    fn color_of(&self, role: Role) -> &str {
+        self.role_colors[role as usize]
+            .as_deref()
+            .unwrap_or(&self.foreground)
+    }
+    /// `foreground`, `background` and the color of each role in `Role` order, `RenderError::InvalidColor`
+    /// if `parse` fails for any of them, even an unused one
+    #[cfg(any(feature = "png", feature = "pdf", feature = "text"))]
+    fn parse_colors<T: Copy>(
+        &self,
+        parse: impl Fn(&str) -> Option<T>,
+    ) -> Result<(T, T, [T; 7]), RenderError> {
+        let parse = |color: &str| parse(color).ok_or(RenderError::InvalidColor);
+        let foreground = parse(&self.foreground)?;
+        let background = parse(&self.background)?;
+        let mut roles = [foreground; 7];
+        for (color, role) in roles.iter_mut().zip(ROLES) {
+            *color = parse(self.color_of(role))?;
+        }
+        Ok((foreground, background, roles))
+    }
+    #[cfg(feature = "svg")]
+    fn shape_of(&self, role: Role) -> Shape {
+        self.role_shapes[role as usize].unwrap_or(self.shape)
+    }
     pub fn foreground(mut self, foreground: String) -> Self {
         self.foreground = foreground;
         self
@@ -56,15 +563,380 @@ impl<'m> RenderData<'m> {
         self.background = background;
         self
     }
-    pub fn toggle_options(mut self, toggle_options: u8) -> Self {
+    /// Replaces `foreground` in `render_svg`, role colors still take precedence
+    pub fn foreground_fill(mut self, fill: Fill) -> Self {
+        self.foreground_fill = Some(fill);
+        self
+    }
+    /// Replaces `background` in `render_svg`
+    pub fn background_fill(mut self, fill: Fill) -> Self {
+        self.background_fill = Some(fill);
+        self
+    }
+    /// Lowest WCAG contrast ratio (1.0-21.0) between any dark module color, including role colors and gradient stops,
+    /// and any background color. Scanners need a clear difference, 3.0 is a sensible `min_ratio`.
+    pub fn check_contrast(&self, min_ratio: f64) -> Result<f64, ContrastError> {
+        fn colors<'a>(color: &'a str, fill: &'a Option<Fill>) -> Vec<&'a str> {
+            match fill {
+                None => vec![color],
+                Some(Fill::LinearGradient { stops, .. } | Fill::RadialGradient { stops }) => {
+                    stops.iter().map(|(_, color)| color.as_str()).collect()
+                }
+                // can't be checked
+                Some(Fill::Image { .. }) => vec![""],
+            }
+        }
+        let luminances = |colors: Vec<&str>| {
+            colors
+                .into_iter()
+                .map(|color| rgb(color).map(relative_luminance))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(ContrastError::InvalidColor)
+        };
+
+        let mut foreground = colors(&self.foreground, &self.foreground_fill);
+        foreground.extend(
+            self.role_colors
+                .iter()
+                .flatten()
+                .map(|color| color.as_str()),
+        );
+        let foreground = luminances(foreground)?;
+        let background = luminances(colors(&self.background, &self.background_fill))?;
+
+        let mut ratio = f64::MAX;
+        for &dark in &foreground {
+            for &light in &background {
+                ratio = ratio.min((dark.max(light) + 0.05) / (dark.min(light) + 0.05));
+            }
+        }
+        match ratio < min_ratio {
+            true => Err(ContrastError::LowContrast),
+            false => Ok(ratio),
+        }
+    }
+    /// Replaces all toggles, e.g. `Toggle::BACKGROUND | Toggle::BACKGROUND_PIXELS`
+    pub fn toggle_options(mut self, toggle_options: Toggle) -> Self {
         self.toggle_options = toggle_options;
         self
     }
+    /// Flips the flags of `toggle`
     pub fn toggle(mut self, toggle: Toggle) -> Self {
-        self.toggle_options ^= 1 << toggle as u8;
+        self.toggle_options ^= toggle;
         self
     }
+    /// Returns true if all flags of `option` are on
     pub fn toggled(&self, option: Toggle) -> bool {
-        (self.toggle_options >> option as u8) & 1 == 1
+        self.toggle_options.has(option)
+    }
+}
+
+/// `#rgb` or `#rrggbb`
+#[cfg(feature = "render")]
+fn rgb(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    let len = match hex.len() {
+        3 => 1,
+        6 => 2,
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        let value = u8::from_str_radix(hex.get(i * len..(i + 1) * len)?, 16).ok()?;
+        Some(if len == 1 { value * 17 } else { value })
+    };
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+/// WCAG relative luminance of an sRGB color
+#[cfg(feature = "render")]
+fn relative_luminance(rgb: [u8; 3]) -> f64 {
+    let [r, g, b] = rgb.map(|channel| {
+        let c = channel as f64 / 255.0;
+        match c <= 0.04045 {
+            true => c / 12.92,
+            false => ((c + 0.055) / 1.055).powf(2.4),
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Error correction codewords reserved for detecting misdecodes in small symbols, ISO/IEC 18004 Table 9
+#[cfg(feature = "render")]
+fn misdecode_protection(version: Version, ecl: ECL) -> usize {
+    match (version.0, ecl) {
+        (1, ECL::Low) => 3,
+        (1, ECL::Medium) | (2, ECL::Low) => 2,
+        (1, _) | (3, ECL::Low) => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(all(test, feature = "render"))]
+mod tests {
+    use super::*;
+    use crate::{decode::decode, generate, generate_micro, generate_rmqr, QrOptions};
+
+    fn logo(width: usize, height: usize) -> Logo {
+        Logo {
+            href: "logo.svg".into(),
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn logo_survives_error_correction() {
+        for (version, ecl, side) in [
+            (Version(5), ECL::High, 9),
+            (Version(10), ECL::Quartile, 11),
+            (Version(2), ECL::Medium, 3),
+        ] {
+            let options = QrOptions::new()
+                .min_version(version)
+                .min_ecl(ecl)
+                .strict_ecl(true);
+            let qr_code = generate("https://github.com/subygan/qrust", &options).unwrap();
+            let render = RenderData::new(&qr_code).logo(logo(side, side)).unwrap();
+
+            // worst case, every hidden module is wrong
+            let mut matrix = qr_code.matrix.clone();
+            for y in 0..matrix.height {
+                for x in 0..matrix.width {
+                    if render.hides(x, y) {
+                        *matrix.get_mut(x, y) = matrix.get(x, y) ^ Module::ON;
+                    }
+                }
+            }
+            let decoded = decode(&matrix).unwrap();
+            assert_eq!(decoded.text, "https://github.com/subygan/qrust");
+            assert!(decoded.corrected > 0);
+        }
+    }
+
+    #[test]
+    fn check_contrast_works() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code);
+        assert_eq!(render.check_contrast(3.0), Ok(21.0));
+
+        let render = RenderData::new(&qr_code).foreground("#777".into());
+        let ratio = render.check_contrast(1.0).unwrap();
+        assert!((ratio - 4.48).abs() < 0.01);
+        assert_eq!(render.check_contrast(4.5), Err(ContrastError::LowContrast));
+
+        let render = RenderData::new(&qr_code).role_color(Role::Finder, "#eee".into());
+        assert_eq!(render.check_contrast(3.0), Err(ContrastError::LowContrast));
+
+        let stops = vec![(0.0, "#000".into()), (1.0, "#ccc".into())];
+        let render = RenderData::new(&qr_code)
+            .foreground_fill(Fill::RadialGradient { stops })
+            .background("#fffffe".into());
+        assert_eq!(render.check_contrast(3.0), Err(ContrastError::LowContrast));
+
+        let render = RenderData::new(&qr_code).background("white".into());
+        assert_eq!(render.check_contrast(3.0), Err(ContrastError::InvalidColor));
+        let image = Fill::Image {
+            href: "paper.png".into(),
+            width: 4,
+            height: 4,
+        };
+        let render = RenderData::new(&qr_code).background_fill(image);
+        assert_eq!(render.check_contrast(3.0), Err(ContrastError::InvalidColor));
+    }
+
+    #[test]
+    fn toggles_work() {
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code);
+        assert!(render.toggled(Toggle::BACKGROUND | Toggle::FOREGROUND_PIXELS));
+        assert!(!render.toggled(Toggle::BACKGROUND_PIXELS));
+
+        let render = render.toggle(Toggle::BACKGROUND | Toggle::BACKGROUND_PIXELS);
+        assert_eq!(
+            render.toggle_options,
+            Toggle::BACKGROUND_PIXELS | Toggle::FOREGROUND_PIXELS
+        );
+        assert!(!render.toggled(Toggle::BACKGROUND));
+        assert!(!render.toggled(Toggle::BACKGROUND | Toggle::FOREGROUND_PIXELS));
+
+        let render = render.toggle_options(Toggle::BACKGROUND);
+        assert!(render.toggled(Toggle::BACKGROUND));
+        assert!(!render.toggled(Toggle::FOREGROUND_PIXELS));
+        assert_eq!(!Toggle::default(), Toggle::BACKGROUND_PIXELS);
+        assert_eq!(!Toggle::all(), Toggle::empty());
+        assert_eq!(!Toggle::empty(), Toggle::all());
+
+        let mut toggles = Toggle::empty();
+        toggles.set(Toggle::FOREGROUND_PIXELS);
+        assert!(toggles.has(Toggle::FOREGROUND_PIXELS));
+    }
+
+    #[test]
+    fn modules_works() {
+        let options = QrOptions::new().min_version(Version(5)).min_ecl(ECL::High);
+        let qr_code = generate("1", &options.strict_ecl(true)).unwrap();
+        let render = RenderData::new(&qr_code)
+            .margin(3)
+            .logo(logo(9, 9))
+            .unwrap();
+        let modules: Vec<_> = render.modules().collect();
+        assert_eq!(render.grid_size(), (43, 43));
+        assert_eq!(modules.len(), 43 * 43);
+
+        let matrix = &qr_code.matrix;
+        for module in &modules {
+            assert_eq!(module, &render.module_at(module.x, module.y));
+            let (Some(x), Some(y)) = (module.x.checked_sub(3), module.y.checked_sub(3)) else {
+                assert_eq!((module.role, module.dark), (None, false));
+                continue;
+            };
+            if x >= 37 || y >= 37 {
+                assert_eq!((module.role, module.dark), (None, false));
+                continue;
+            }
+            assert_eq!(module.role, Some(Role::of(matrix.get(x, y))));
+            assert_eq!(module.dark, matrix.get(x, y).has(Module::ON));
+            assert_eq!(module.hidden, render.hides(x, y));
+        }
+        assert_eq!(modules.iter().filter(|module| module.hidden).count(), 9 * 9);
+        assert_eq!(render.module_at(50, 0).role, None);
+    }
+
+    #[test]
+    fn custom_renderer_works() {
+        /// One character per module, the first letter of its color
+        struct Letters;
+        impl Renderer for Letters {
+            type Output = String;
+
+            fn render(&self, render: &RenderData) -> String {
+                let width = render.grid_size().0;
+                let mut result = String::new();
+                for module in render.modules() {
+                    result.push_str(&render.color(&module)[..1]);
+                    if module.x == width - 1 {
+                        result.push('\n');
+                    }
+                }
+                result
+            }
+        }
+
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code)
+            .margin(1)
+            .foreground("black".into())
+            .background("white".into())
+            .role_color(Role::FinderCenter, "red".into());
+        let letters = render.render(Letters);
+        let rows: Vec<_> = letters.lines().collect();
+        assert_eq!(rows.len(), 13);
+        assert_eq!(rows[0], "w".repeat(13));
+        assert_eq!(&rows[1][..9], "wbbbbbbbw");
+        assert_eq!(&rows[4][..9], "wbwrrrwbw");
+    }
+
+    #[test]
+    fn physical_size_works() {
+        // 25 modules
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code);
+        assert_eq!(render.module_mm(), None);
+
+        // 295.3 px wide, 11 px per module fits 26.8 modules
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::Width(25.0), 300.0)
+            .unwrap();
+        assert_eq!(render.layout(), (11, 2));
+        // 23 modules, 251.6 px wide, 10 px per module fits 25.2 modules, 1 module wider margin
+        let render = RenderData::new(&qr_code)
+            .margin(1)
+            .physical_size(PhysicalSize::Width(21.3), 300.0)
+            .unwrap();
+        assert_eq!((render.layout(), render.width()), ((10, 2), 250));
+
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::ModuleSize(0.5), 600.0)
+            .unwrap();
+        assert_eq!(render.layout(), (12, 2));
+        assert!((render.module_mm().unwrap() - 0.508).abs() < 1e-9);
+        assert!(!render.is_below_printable_minimum());
+        assert!(!RenderData::new(&qr_code).is_below_printable_minimum());
+
+        // too small to print reliably, but still rendered
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::ModuleSize(0.2), 1200.0)
+            .unwrap();
+        assert_eq!(render.layout().0, 9);
+        assert!(render.is_below_printable_minimum());
+        // 59.1 px wide, 2 px per module
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::Width(5.0), 300.0)
+            .unwrap();
+        assert_eq!(render.layout().0, 2);
+        assert!(render.is_below_printable_minimum());
+
+        // R7x43, the margin grows on every side
+        let rmqr = generate_rmqr("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&rmqr)
+            .physical_size(PhysicalSize::Width(25.0), 300.0)
+            .unwrap();
+        assert_eq!(render.layout(), (6, 3));
+        assert_eq!((render.width(), render.height()), (294, 78));
+
+        // the builder order doesn't matter and setting the size again replaces it
+        let width = PhysicalSize::Width(21.3);
+        let render = RenderData::new(&qr_code)
+            .physical_size(width, 300.0)
+            .unwrap()
+            .margin(1)
+            .unit(5);
+        assert_eq!((render.layout(), render.width()), ((10, 2), 250));
+        let render = render.physical_size(width, 300.0).unwrap();
+        assert_eq!((render.layout(), render.width()), ((10, 2), 250));
+        assert_eq!(render.resolved().layout(), (10, 2));
+        let render = render
+            .physical_size(PhysicalSize::ModuleSize(0.5), 600.0)
+            .unwrap();
+        assert_eq!(render.layout(), (12, 1));
+
+        let size = |size, dpi| RenderData::new(&qr_code).physical_size(size, dpi).err();
+        assert_eq!(
+            size(PhysicalSize::Width(1.0), 300.0),
+            Some(SizeError::InvalidSize)
+        );
+        assert_eq!(
+            size(PhysicalSize::ModuleSize(-1.0), 300.0),
+            Some(SizeError::InvalidSize)
+        );
+        assert_eq!(
+            size(PhysicalSize::ModuleSize(1.0), f64::NAN),
+            Some(SizeError::InvalidSize)
+        );
+    }
+
+    #[test]
+    fn logo_errors() {
+        let options = QrOptions::new().min_version(Version(5)).min_ecl(ECL::High);
+        let qr_code = generate("https://github.com/subygan/qrust", &options).unwrap();
+        let render = |logo| RenderData::new(&qr_code).logo(logo).err();
+        assert_eq!(
+            render(logo(21, 21)),
+            Some(LogoError::ExceedsErrorCorrection)
+        );
+        assert_eq!(render(logo(0, 5)), Some(LogoError::InvalidSize));
+        assert_eq!(render(logo(38, 5)), Some(LogoError::InvalidSize));
+
+        let options = QrOptions::new()
+            .min_version(Version(5))
+            .min_ecl(ECL::Low)
+            .strict_ecl(true);
+        let qr_code = generate("https://github.com/subygan/qrust", &options).unwrap();
+        let render = RenderData::new(&qr_code).logo(logo(9, 9));
+        assert_eq!(render.err(), Some(LogoError::ExceedsErrorCorrection));
+
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code).logo(logo(3, 3));
+        assert_eq!(render.err(), Some(LogoError::UnsupportedSymbol));
     }
 }
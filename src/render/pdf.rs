@@ -0,0 +1,399 @@
+use super::{rgb, RenderData, Renderer, Toggle};
+use crate::RenderError;
+
+/// Points per millimetre
+const PT_PER_MM: f64 = 72.0 / 25.4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PrintColor {
+    Rgb([f64; 3]),
+    Cmyk([f64; 4]),
+}
+
+/// Single page PDF, `module_size` in millimetres, or `module_mm` if `physical_size` was set. Colors are `#rgb`, `#rrggbb` or `device-cmyk(c m y k)` with
+/// 0.0-1.0 or percentage components, others are a `RenderError::InvalidColor`.
+/// Modules are drawn as merged rectangles, shapes, fills and `unit` are ignored, and the logo area is left empty.
+/// `RenderError::InvalidSize` if `module_size` isn't a positive number.
+pub fn render_pdf(render: &RenderData, module_size: f64) -> Result<Vec<u8>, RenderError> {
+    let (width, height, content) = draw(render, module_size, Syntax::Pdf)?;
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << >> /Contents 4 0 R >>",
+            num(width),
+            num(height)
+        ),
+        format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ),
+    ];
+
+    // binary comment so transfer tools don't treat it as text
+    let mut output = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(output.len());
+        output.extend(format!("{} 0 obj\n{object}\nendobj\n", i + 1).bytes());
+    }
+
+    let xref = output.len();
+    output.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+    for offset in offsets {
+        output.extend(format!("{offset:010} 00000 n \n").bytes());
+    }
+    output.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .bytes(),
+    );
+    Ok(output)
+}
+
+/// Encapsulated PostScript, same options as `render_pdf`
+pub fn render_eps(render: &RenderData, module_size: f64) -> Result<String, RenderError> {
+    let (width, height, content) = draw(render, module_size, Syntax::Eps)?;
+    Ok(format!(
+        "%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 {} {}\n%%HiResBoundingBox: 0 0 {} {}\n%%Pages: 1\n%%EndComments\n{content}showpage\n%%EOF\n",
+        width.ceil(),
+        height.ceil(),
+        num(width),
+        num(height)
+    ))
+}
+
+/// `Renderer` of `render_pdf`
+#[derive(Clone, Copy, Debug)]
+pub struct Pdf {
+    /// In millimetres
+    pub module_size: f64,
+}
+
+/// `Renderer` of `render_eps`
+#[derive(Clone, Copy, Debug)]
+pub struct Eps {
+    /// In millimetres
+    pub module_size: f64,
+}
+
+impl Renderer for Pdf {
+    type Output = Result<Vec<u8>, RenderError>;
+
+    fn render(&self, render: &RenderData) -> Self::Output {
+        render_pdf(render, self.module_size)
+    }
+}
+
+impl Renderer for Eps {
+    type Output = Result<String, RenderError>;
+
+    fn render(&self, render: &RenderData) -> Self::Output {
+        render_eps(render, self.module_size)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Pdf,
+    Eps,
+}
+
+/// Page width and height in points, and the drawing operators in module coordinates from the top left
+fn draw(
+    render: &RenderData,
+    module_size: f64,
+    syntax: Syntax,
+) -> Result<(f64, f64, String), RenderError> {
+    let module_size = render.module_mm().unwrap_or(module_size);
+    if !(module_size > 0.0 && module_size.is_finite()) {
+        return Err(RenderError::InvalidSize);
+    }
+    let (columns, rows) = render.grid_size();
+    let scale = module_size * PT_PER_MM;
+    let (width, height) = (columns as f64 * scale, rows as f64 * scale);
+
+    let mut content = match syntax {
+        Syntax::Pdf => format!("{} 0 0 {} 0 {} cm\n", num(scale), num(-scale), num(height)),
+        Syntax::Eps => format!(
+            "0 {} translate {} {} scale\n",
+            num(height),
+            num(scale),
+            num(-scale)
+        ),
+    };
+    let (_, background, roles) = render.parse_colors(print_color)?;
+
+    if render.toggled(Toggle::BACKGROUND) {
+        push_color(&mut content, background, syntax);
+        push_rectangle(&mut content, [0, 0, columns, rows], syntax);
+    }
+
+    let color_at = |x: usize, y: usize| {
+        let module = render.module_at(x, y);
+        match module.role {
+            Some(role) if module.dark && !module.hidden => Some(roles[role as usize]),
+            _ => None,
+        }
+    };
+    let mut colors: Vec<PrintColor> = Vec::new();
+    for module in render.modules() {
+        match color_at(module.x, module.y) {
+            Some(color) if !colors.contains(&color) => colors.push(color),
+            _ => {}
+        }
+    }
+
+    for color in colors {
+        push_color(&mut content, color, syntax);
+        let is_in = |x: usize, y: usize| color_at(x, y) == Some(color);
+        for rectangle in rectangles(columns, rows, is_in) {
+            push_rectangle(&mut content, rectangle, syntax);
+        }
+    }
+    Ok((width, height, content))
+}
+
+/// Horizontal runs, merged with identical runs in the rows below, as [x, y, width, height]
+fn rectangles(
+    width: usize,
+    height: usize,
+    is_in: impl Fn(usize, usize) -> bool,
+) -> Vec<[usize; 4]> {
+    let mut open: Vec<[usize; 4]> = Vec::new();
+    let mut done = Vec::new();
+    for y in 0..height {
+        let mut next = Vec::new();
+        let mut x = 0;
+        while x < width {
+            if !is_in(x, y) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < width && is_in(x, y) {
+                x += 1;
+            }
+            match open.iter().position(|r| r[0] == start && r[2] == x - start) {
+                Some(i) => {
+                    let mut rectangle = open.swap_remove(i);
+                    rectangle[3] += 1;
+                    next.push(rectangle);
+                }
+                None => next.push([start, y, x - start, 1]),
+            }
+        }
+        done.append(&mut open);
+        open = next;
+    }
+    done.append(&mut open);
+    done.sort_unstable_by_key(|&[x, y, ..]| (y, x));
+    done
+}
+
+fn push_color(content: &mut String, color: PrintColor, syntax: Syntax) {
+    let operator = match (color, syntax) {
+        (PrintColor::Rgb(_), Syntax::Pdf) => "rg",
+        (PrintColor::Cmyk(_), Syntax::Pdf) => "k",
+        (PrintColor::Rgb(_), Syntax::Eps) => "setrgbcolor",
+        (PrintColor::Cmyk(_), Syntax::Eps) => "setcmykcolor",
+    };
+    let components = match &color {
+        PrintColor::Rgb(rgb) => &rgb[..],
+        PrintColor::Cmyk(cmyk) => &cmyk[..],
+    };
+    for component in components {
+        content.push_str(&format!("{} ", num(*component)));
+    }
+    content.push_str(operator);
+    content.push('\n');
+}
+
+fn push_rectangle(content: &mut String, [x, y, w, h]: [usize; 4], syntax: Syntax) {
+    content.push_str(&match syntax {
+        Syntax::Pdf => format!("{x} {y} {w} {h} re f\n"),
+        Syntax::Eps => format!("{x} {y} {w} {h} rectfill\n"),
+    });
+}
+
+/// Hex colors or `device-cmyk(c m y k)`
+fn print_color(color: &str) -> Option<PrintColor> {
+    if let Some(hex) = rgb(color) {
+        return Some(PrintColor::Rgb(hex.map(|c| c as f64 / 255.0)));
+    }
+
+    let components = color
+        .strip_prefix("device-cmyk(")?
+        .strip_suffix(')')?
+        .split([' ', ','])
+        .filter(|c| !c.is_empty())
+        .map(|c| match c.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().ok().map(|p| p / 100.0),
+            None => c.parse::<f64>().ok(),
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let cmyk: [f64; 4] = components.try_into().ok()?;
+    cmyk.iter()
+        .all(|c| (0.0..=1.0).contains(c))
+        .then_some(PrintColor::Cmyk(cmyk))
+}
+
+/// Rounds to 4 decimals, whole numbers print without a decimal point
+fn num(value: f64) -> f64 {
+    (value * 10000.0).round() / 10000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decode::decode_bools,
+        generate,
+        render::{PhysicalSize, Role},
+        QrOptions,
+    };
+
+    /// Dark modules from the rectangles after the background, with the given fill operator
+    fn modules(content: &str, operator: &str, width: usize, margin: usize) -> Vec<bool> {
+        let mut grid = vec![false; width * width];
+        for line in content.lines().filter(|line| line.ends_with(operator)) {
+            let [x, y, w, h] = line
+                .split(' ')
+                .take(4)
+                .map(|n| n.parse::<usize>().unwrap())
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap();
+            if w == width + margin * 2 {
+                continue;
+            }
+            for y in y..y + h {
+                for x in x..x + w {
+                    grid[(y - margin) * width + x - margin] = true;
+                }
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn print_color_works() {
+        assert_eq!(print_color("#fff"), Some(PrintColor::Rgb([1.0; 3])));
+        assert_eq!(
+            print_color("device-cmyk(0 50% 1 0.25)"),
+            Some(PrintColor::Cmyk([0.0, 0.5, 1.0, 0.25]))
+        );
+        assert_eq!(
+            print_color("device-cmyk(1, 0, 0, 0)"),
+            Some(PrintColor::Cmyk([1.0, 0.0, 0.0, 0.0]))
+        );
+        assert_eq!(print_color("device-cmyk(0 0 0)"), None);
+        assert_eq!(print_color("device-cmyk(0 0 0 2)"), None);
+        assert_eq!(print_color("black"), None);
+    }
+
+    #[test]
+    fn rectangles_merge() {
+        // two columns, then an L
+        let cells = ["##..", "##..", "#...", "####"];
+        let is_in = |x: usize, y: usize| cells[y].as_bytes()[x] == b'#';
+        assert_eq!(
+            rectangles(4, 4, is_in),
+            [[0, 0, 2, 2], [0, 2, 1, 1], [0, 3, 4, 1]]
+        );
+    }
+
+    #[test]
+    fn render_pdf_works() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let width = qr_code.matrix.width;
+        let render = RenderData::new(&qr_code)
+            .margin(4)
+            .foreground("device-cmyk(0 0 0 100%)".into());
+        let pdf = render_pdf(&render, 0.5).unwrap();
+        // one char per byte, to keep the offsets
+        let text: String = pdf
+            .iter()
+            .map(|&b| if b.is_ascii() { b as char } else { '?' })
+            .collect();
+
+        // (25 + 8) * 0.5 mm
+        assert!(text.contains("/MediaBox [0 0 46.7717 46.7717]"));
+        assert!(text.contains("\n1 1 1 rg\n0 0 33 33 re f\n0 0 0 1 k\n"));
+
+        // every xref offset points at its object
+        let xref = text.rfind("\nxref\n").unwrap() + 1;
+        assert!(text.contains(&format!("startxref\n{xref}\n")));
+        for (i, line) in text[xref..].lines().skip(3).take(4).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+        let start = text.find("stream\n").unwrap() + 7;
+        let end = text.find("endstream").unwrap();
+        assert!(text.contains(&format!("<< /Length {} >>", end - start)));
+
+        let grid = modules(&text[start..end], " re f", width, 4);
+        let decoded = decode_bools(&grid, width).unwrap();
+        assert_eq!(decoded.text, "https://github.com/subygan/qrust");
+        // merged, far fewer rectangles than dark modules
+        assert!(text.matches(" re f").count() * 2 < grid.iter().filter(|&&on| on).count());
+    }
+
+    #[test]
+    fn render_eps_works() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let width = qr_code.matrix.width;
+        let render = RenderData::new(&qr_code).foreground("#f00".into());
+        let eps = render_eps(&render, 1.0).unwrap();
+
+        assert!(eps.starts_with(
+            "%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 83 83\n%%HiResBoundingBox: 0 0 82.2047 82.2047\n"
+        ));
+        assert!(eps.contains("\n1 0 0 setrgbcolor\n"));
+        assert!(eps.ends_with("showpage\n%%EOF\n"));
+
+        let grid = modules(&eps, " rectfill", width, 2);
+        let decoded = decode_bools(&grid, width).unwrap();
+        assert_eq!(decoded.text, "https://github.com/subygan/qrust");
+    }
+
+    #[test]
+    fn module_size_works() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code);
+        for module_size in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                render_pdf(&render, module_size),
+                Err(RenderError::InvalidSize)
+            );
+            assert_eq!(
+                render_eps(&render, module_size),
+                Err(RenderError::InvalidSize)
+            );
+        }
+
+        // 6 px at 300 DPI is 0.508 mm, the argument is ignored
+        let render = render
+            .physical_size(PhysicalSize::ModuleSize(0.5), 300.0)
+            .unwrap();
+        let eps = render_eps(&render, 1.0).unwrap();
+        // 25 modules * 0.508 mm
+        assert!(eps.contains("%%HiResBoundingBox: 0 0 36 36\n"));
+        assert_eq!(render_eps(&render, f64::NAN), Ok(eps));
+    }
+
+    #[test]
+    fn render_pdf_rejects_unparseable_colors() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        for render in [
+            RenderData::new(&qr_code).foreground("black".into()),
+            RenderData::new(&qr_code).role_color(Role::Finder, "device-cmyk(0 0 0)".into()),
+        ] {
+            assert_eq!(render_pdf(&render, 1.0), Err(RenderError::InvalidColor));
+            assert_eq!(render_eps(&render, 1.0), Err(RenderError::InvalidColor));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use super::{rgb, RenderData, Renderer};
+use crate::RenderError;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const CRC_TABLE: [u32; 256] = crc_table();
+/// Max length of a deflate stored block
+const MAX_BLOCK: usize = 65535;
+
+/// `Renderer` of `render_png`
+#[derive(Clone, Copy, Debug)]
+pub struct Png;
+
+impl Renderer for Png {
+    type Output = Result<Vec<u8>, RenderError>;
+
+    fn render(&self, render: &RenderData) -> Self::Output {
+        render_png(render)
+    }
+}
+
+/// 1-bit grayscale if the colors are black and white, otherwise 8-bit grayscale. Role colors are converted too.
+/// The logo area is background, the logo itself is only drawn by the SVG renderers.
+/// Colors must be hex (`#rgb` or `#rrggbb`), others are a `RenderError::InvalidColor`.
+pub fn render_png(render: &RenderData) -> Result<Vec<u8>, RenderError> {
+    let render = &render.resolved();
+    let (_, background, roles) = render.parse_colors(gray)?;
+    let bit_depth = match roles
+        .iter()
+        .chain([&background])
+        .all(|&g| g == 0 || g == 255)
+    {
+        true => 1,
+        false => 8,
+    };
+
+    let width = render.width();
+    let height = render.height();
+
+    // filter type byte + packed pixels
+    let stride = 1 + (width * bit_depth).div_ceil(8);
+    let mut scanlines = Vec::with_capacity(stride * height);
+    for y in 0..height {
+        scanlines.push(0);
+        let mut byte = 0;
+        for x in 0..width {
+            let module = render.module_at(x / render.unit, y / render.unit);
+            // the logo area is left empty to composite a logo onto
+            let value = match module.role {
+                Some(role) if module.dark && !module.hidden => roles[role as usize],
+                _ => background,
+            };
+            if bit_depth == 8 {
+                scanlines.push(value);
+                continue;
+            }
+            byte |= (value >> 7) << (7 - x % 8);
+            if x % 8 == 7 || x == width - 1 {
+                scanlines.push(byte);
+                byte = 0;
+            }
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // bit depth, grayscale, deflate, no filter, no interlace
+    header.extend([bit_depth as u8, 0, 0, 0, 0]);
+
+    let mut output = SIGNATURE.to_vec();
+    push_chunk(&mut output, b"IHDR", &header);
+    if let Some(dpi) = render.dpi {
+        output.extend(phys_chunk(dpi));
+    }
+    push_chunk(&mut output, b"IDAT", &zlib_stored(&scanlines));
+    push_chunk(&mut output, b"IEND", &[]);
+    Ok(output)
+}
+
+/// pHYs chunk, goes between IHDR and IDAT
+pub(super) fn phys_chunk(dpi: f64) -> Vec<u8> {
+    let pixels_per_metre = (dpi / 0.0254).round() as u32;
+    let mut data = Vec::with_capacity(9);
+    data.extend(pixels_per_metre.to_be_bytes());
+    data.extend(pixels_per_metre.to_be_bytes());
+    // unit is the metre
+    data.push(1);
+
+    let mut output = Vec::with_capacity(21);
+    push_chunk(&mut output, b"pHYs", &data);
+    output
+}
+
+fn push_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend((data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend(kind);
+    output.extend(data);
+    let crc = crc32(&output[start..]);
+    output.extend(crc.to_be_bytes());
+}
+
+/// Uncompressed deflate, QR codes are small enough that compression isn't worth the code
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let num_blocks = data.len().div_ceil(MAX_BLOCK).max(1);
+    let mut output = Vec::with_capacity(data.len() + num_blocks * 5 + 6);
+    // deflate, 32K window, no dictionary, fastest
+    output.extend([0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        output.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        // BFINAL, BTYPE = 00 (stored)
+        output.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        output.extend(len.to_le_bytes());
+        output.extend((!len).to_le_bytes());
+        output.extend(block);
+    }
+
+    output.extend(adler32(data).to_be_bytes());
+    output
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // 5552 is the most bytes before b can overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff;
+    for &byte in data {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => 0xedb8_8320 ^ (crc >> 1),
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Luma of a `#rgb` or `#rrggbb` color
+fn gray(color: &str) -> Option<u8> {
+    let [r, g, b] = rgb(color)?.map(u32::from);
+    Some(((299 * r + 587 * g + 114 * b) / 1000) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate,
+        matrix::Module,
+        render::{PhysicalSize, Role},
+        QrOptions,
+    };
+
+    #[test]
+    fn crc32_and_adler32_work() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn gray_works() {
+        assert_eq!(gray("#000"), Some(0));
+        assert_eq!(gray("#ffffff"), Some(255));
+        assert_eq!(gray("#808080"), Some(128));
+        assert_eq!(gray("#f00"), Some(76));
+        assert_eq!(gray("red"), None);
+        assert_eq!(gray("#12345"), None);
+    }
+
+    #[test]
+    fn render_png_physical_size() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::ModuleSize(0.5), 300.0)
+            .unwrap();
+        let png = render_png(&render).unwrap();
+        // 11811 pixels per metre, twice, and the metre unit
+        assert_eq!(&png[33..41], b"\0\0\0\x09pHYs");
+        assert_eq!(&png[41..50], [0, 0, 0x2e, 0x23, 0, 0, 0x2e, 0x23, 1]);
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.width() as usize, render.width());
+    }
+
+    #[test]
+    fn render_png_decodes() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        for (foreground, background, unit, margin) in [
+            ("#000", "#fff", 1, 2),
+            ("#fff", "#000", 3, 4),
+            ("#123456", "#eeeeee", 2, 0),
+        ] {
+            let render = RenderData::new(&qr_code)
+                .foreground(foreground.into())
+                .background(background.into())
+                .unit(unit)
+                .margin(margin);
+            let png = render_png(&render).unwrap();
+            let image = image::load_from_memory(&png).unwrap().to_luma8();
+            assert_eq!(image.width() as usize, render.width());
+            assert_eq!(image.height() as usize, render.height());
+
+            let (on, off) = (gray(foreground).unwrap(), gray(background).unwrap());
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let x = (x as usize / unit).checked_sub(margin);
+                let y = (y as usize / unit).checked_sub(margin);
+                let module_on = match (x, y) {
+                    (Some(x), Some(y)) if x < qr_code.matrix.width && y < qr_code.matrix.height => {
+                        qr_code.matrix.get(x, y).has(Module::ON)
+                    }
+                    _ => false,
+                };
+                assert_eq!(pixel.0[0], if module_on { on } else { off });
+            }
+        }
+    }
+
+    #[test]
+    fn render_png_role_colors() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code)
+            .margin(0)
+            .role_color(Role::FinderCenter, "#808080".into());
+        let image = image::load_from_memory(&render_png(&render).unwrap())
+            .unwrap()
+            .to_luma8();
+        assert_eq!(image.get_pixel(0, 0).0[0], 0);
+        assert_eq!(image.get_pixel(3, 3).0[0], 128);
+        assert_eq!(image.get_pixel(1, 1).0[0], 255);
+
+        let render = RenderData::new(&qr_code).role_color(Role::Data, "#fff".into());
+        let png = render_png(&render).unwrap();
+        assert_eq!(png[24], 1);
+    }
+
+    #[test]
+    fn render_png_rejects_unparseable_colors() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        for render in [
+            RenderData::new(&qr_code).foreground("red".into()),
+            RenderData::new(&qr_code).background("rgb(255, 255, 255)".into()),
+            RenderData::new(&qr_code).role_color(Role::Finder, "#12345".into()),
+        ] {
+            assert_eq!(render_png(&render), Err(RenderError::InvalidColor));
+        }
+    }
+
+    #[test]
+    fn render_png_splits_stored_blocks() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code).foreground("#333".into()).unit(10);
+        assert!(render.width() * render.height() > MAX_BLOCK);
+        let image = image::load_from_memory(&render_png(&render).unwrap()).unwrap();
+        assert_eq!(image.width() as usize, render.width());
+    }
+}
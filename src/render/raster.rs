@@ -0,0 +1,147 @@
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{Options, Tree},
+};
+
+use super::{png::phys_chunk, svg::render_svg, RenderData, Renderer};
+use crate::RasterError;
+
+/// `Renderer` of `render_rgba`
+#[derive(Clone, Copy, Debug)]
+pub struct Rgba;
+
+/// `Renderer` of `render_raster_png`
+#[derive(Clone, Copy, Debug)]
+pub struct RasterPng;
+
+impl Renderer for Rgba {
+    type Output = Result<Vec<u8>, RasterError>;
+
+    fn render(&self, render: &RenderData) -> Self::Output {
+        render_rgba(render)
+    }
+}
+
+impl Renderer for RasterPng {
+    type Output = Result<Vec<u8>, RasterError>;
+
+    fn render(&self, render: &RenderData) -> Self::Output {
+        render_raster_png(render)
+    }
+}
+
+/// Straight (not premultiplied) RGBA of `render_svg`, `render.width()` x `render.height()` pixels
+pub fn render_rgba(render: &RenderData) -> Result<Vec<u8>, RasterError> {
+    let pixmap = rasterize(
+        &render_svg(render),
+        render.width() as u32,
+        render.height() as u32,
+    )?;
+    Ok(pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect())
+}
+
+/// RGBA PNG of `render_svg`, unlike `png::render_png` this keeps colors, transparency and anti-aliasing
+pub fn render_raster_png(render: &RenderData) -> Result<Vec<u8>, RasterError> {
+    let mut png = svg_to_png(
+        &render_svg(render),
+        render.width() as u32,
+        render.height() as u32,
+    )?;
+    if let Some(dpi) = render.dpi {
+        // right after the signature and IHDR
+        png.splice(33..33, phys_chunk(dpi));
+    }
+    Ok(png)
+}
+
+/// Rasterises any SVG, e.g. an edited `render_svg` output, stretched to `width` x `height`
+pub fn svg_to_png(svg: &str, width: u32, height: u32) -> Result<Vec<u8>, RasterError> {
+    rasterize(svg, width, height)?
+        .encode_png()
+        .map_err(|_| RasterError::EncodingFailed)
+}
+
+fn rasterize(svg: &str, width: u32, height: u32) -> Result<Pixmap, RasterError> {
+    let tree = Tree::from_str(svg, &Options::default()).map_err(|_| RasterError::InvalidSvg)?;
+    let mut pixmap = Pixmap::new(width, height).ok_or(RasterError::InvalidSize)?;
+    let size = tree.size();
+    let transform =
+        Transform::from_scale(width as f32 / size.width(), height as f32 / size.height());
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    Ok(pixmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, generate_rmqr, matrix::Module, render::PhysicalSize, QrOptions};
+
+    #[test]
+    fn render_rgba_matches_modules() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let rmqr = generate_rmqr("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        for (qr_code, unit, margin) in [(&qr_code, 1, 2), (&qr_code, 3, 0), (&rmqr, 2, 1)] {
+            let render = RenderData::new(qr_code)
+                .foreground("#102030".into())
+                .background("#f0e0d0".into())
+                .unit(unit)
+                .margin(margin);
+            let rgba = render_rgba(&render).unwrap();
+            assert_eq!(rgba.len(), render.width() * render.height() * 4);
+
+            for (i, pixel) in rgba.chunks(4).enumerate() {
+                let x = i % render.width() / unit;
+                let y = i / render.width() / unit;
+                let module_on = x >= margin
+                    && y >= margin
+                    && x - margin < qr_code.matrix.width
+                    && y - margin < qr_code.matrix.height
+                    && qr_code.matrix.get(x - margin, y - margin).has(Module::ON);
+                let expected = if module_on {
+                    [0x10, 0x20, 0x30, 0xff]
+                } else {
+                    [0xf0, 0xe0, 0xd0, 0xff]
+                };
+                assert_eq!(pixel, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn render_raster_png_decodes() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code).unit(4);
+        let png = render_raster_png(&render).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.width() as usize, render.width());
+        assert_eq!(image.height() as usize, render.height());
+        assert_eq!(image.into_raw(), render_rgba(&render).unwrap());
+    }
+
+    #[test]
+    fn render_raster_png_physical_size() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::Width(25.0), 300.0)
+            .unwrap();
+        let png = render_raster_png(&render).unwrap();
+        assert_eq!(&png[37..41], b"pHYs");
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.width() as usize, render.width());
+    }
+
+    #[test]
+    fn svg_to_png_errors() {
+        assert_eq!(svg_to_png("<svg", 10, 10), Err(RasterError::InvalidSvg));
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1 1"/>"#;
+        assert_eq!(svg_to_png(svg, 0, 10), Err(RasterError::InvalidSize));
+        assert!(svg_to_png(svg, 10, 10).is_ok());
+    }
+}
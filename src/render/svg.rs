@@ -1,30 +1,98 @@
 use crate::matrix::Module;
 
-use super::{RenderData, Toggle};
+use super::{Fill, FinderShape, PathMode, PlacedModule, RenderData, Renderer, Role, Shape, Toggle};
+
+/// `Renderer` of `render_svg`
+#[derive(Clone, Copy, Debug)]
+pub struct Svg;
+
+impl Renderer for Svg {
+    type Output = String;
+
+    fn render(&self, render: &RenderData) -> String {
+        render_svg(render)
+    }
+}
 
 pub fn render_svg(render: &RenderData) -> String {
-    let mut output = String::with_capacity(40 * (render.width() * render.width()) / 2);
+    let render = &render.resolved();
+    let mut svg_render = render.clone();
+    let mut size = String::new();
+    if render.path_mode == PathMode::Merged {
+        // drawn in modules, the viewBox scales them to pixels
+        size = format!(
+            r#" width="{}" height="{}""#,
+            render.width(),
+            render.height()
+        );
+        svg_render.unit = 1;
+    }
+    if let Some(dpi) = render.dpi {
+        let mm = |pixels: usize| num(pixels as f64 / dpi * 25.4);
+        size = format!(
+            r#" width="{}mm" height="{}mm""#,
+            mm(render.width()),
+            mm(render.height())
+        );
+    }
+
+    let mut defs = String::new();
+    if let Some(fill) = &render.foreground_fill {
+        push_fill(&mut defs, &svg_render, "qrust-foreground", fill);
+        svg_render.foreground = "url(#qrust-foreground)".into();
+    }
+    if let Some(fill) = &render.background_fill {
+        push_fill(&mut defs, &svg_render, "qrust-background", fill);
+        svg_render.background = "url(#qrust-background)".into();
+    }
+    svg(&svg_render, &size, &defs)
+}
+
+fn svg(render: &RenderData, size: &str, defs: &str) -> String {
+    let mut output = String::with_capacity(40 * (render.width() * render.height()) / 2);
     output.push_str(&format!(
-        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}">"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}"{size}>"#,
         render.width(),
-        render.width()
+        render.height()
     ));
+    if !defs.is_empty() {
+        output.push_str(&format!("<defs>{defs}</defs>"));
+    }
 
-    if render.toggled(Toggle::Background) {
+    if render.toggled(Toggle::BACKGROUND) {
         output.push_str(&format!(
             r#"<rect width="{}" height="{}" fill="{}"/>"#,
             render.width(),
-            render.width(),
-            render.background
+            render.height(),
+            escape(&render.background)
         ));
     }
 
-    if render.toggled(Toggle::BackgroundPixels) {
-        render_pixels(render, &mut output, false);
+    if let Some((x, y, logo)) = render.logo_area() {
+        output.push_str(&format!(
+            r#"<image href="{}" x="{}" y="{}" width="{}" height="{}"/>"#,
+            escape(&logo.href),
+            (x + render.margin) * render.unit,
+            (y + render.margin) * render.unit,
+            logo.width * render.unit,
+            logo.height * render.unit
+        ));
     }
 
-    if render.toggled(Toggle::ForegroundPixels) {
-        render_pixels(render, &mut output, true);
+    let eyes = match render.finder_shape {
+        FinderShape::Modules => Vec::new(),
+        _ => find_eyes(render),
+    };
+
+    if render.toggled(Toggle::BACKGROUND_PIXELS) {
+        render_pixels(render, &eyes, &mut output, false);
+    }
+
+    if render.toggled(Toggle::FOREGROUND_PIXELS) {
+        render_pixels(render, &eyes, &mut output, true);
+        if !eyes.is_empty() {
+            render_eyes(render, &eyes, &mut output);
+        }
     }
 
     output.push_str("</svg>");
@@ -32,34 +100,631 @@ pub fn render_svg(render: &RenderData) -> String {
     output
 }
 
-fn render_pixels(render: &RenderData, output: &mut String, on: bool) {
-    output.push_str(&format!(
-        "<path fill=\"{}\" d=\"",
-        if on {
-            &render.foreground
-        } else {
-            &render.background
+/// Paint server over the whole image, in the same units as the paths
+fn push_fill(defs: &mut String, render: &RenderData, id: &str, fill: &Fill) {
+    let (width, height) = (render.width() as f64, render.height() as f64);
+    let stops = |stops: &[(f64, String)]| {
+        stops
+            .iter()
+            .map(|(offset, color)| {
+                format!(
+                    r#"<stop offset="{}" stop-color="{}"/>"#,
+                    num(*offset),
+                    escape(color)
+                )
+            })
+            .collect::<String>()
+    };
+
+    defs.push_str(&match fill {
+        Fill::LinearGradient { from, to, stops: s } => format!(
+            r#"<linearGradient id="{id}" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}">{}</linearGradient>"#,
+            num(from.0 * width),
+            num(from.1 * height),
+            num(to.0 * width),
+            num(to.1 * height),
+            stops(s)
+        ),
+        Fill::RadialGradient { stops: s } => format!(
+            r#"<radialGradient id="{id}" gradientUnits="userSpaceOnUse" cx="{}" cy="{}" r="{}">{}</radialGradient>"#,
+            num(width / 2.0),
+            num(height / 2.0),
+            num(width.max(height) / 2.0),
+            stops(s)
+        ),
+        Fill::Image {
+            href,
+            width,
+            height,
+        } => {
+            let (width, height) = (width * render.unit, height * render.unit);
+            format!(
+                r#"<pattern id="{id}" patternUnits="userSpaceOnUse" width="{width}" height="{height}"><image href="{}" width="{width}" height="{height}"/></pattern>"#,
+                escape(href)
+            )
         }
-    ));
+    });
+}
 
-    for y in 0..render.qr_code.matrix.width {
-        for x in 0..render.qr_code.matrix.width {
-            let module_on = render.qr_code.matrix.get(x, y).has(Module::ON);
+/// (x, y, side) of every finder pattern, including the rMQR sub-pattern, found from their centers
+fn find_eyes(render: &RenderData) -> Vec<(usize, usize, usize)> {
+    let matrix = &render.qr_code.matrix;
+    let is_center = |x: usize, y: usize| matrix.get(x, y).has(Module::FINDER_CENTER);
 
-            if module_on != on {
+    let mut eyes = Vec::new();
+    for y in 0..matrix.height {
+        for x in 0..matrix.width {
+            // top left of each center block
+            if !is_center(x, y) || (x > 0 && is_center(x - 1, y)) || (y > 0 && is_center(x, y - 1))
+            {
                 continue;
             }
+            let center = (x..matrix.width).take_while(|&x| is_center(x, y)).count();
+            eyes.push((x - 2, y - 2, center + 4));
+        }
+    }
+    eyes
+}
+
+fn in_eye(eyes: &[(usize, usize, usize)], x: usize, y: usize) -> bool {
+    eyes.iter()
+        .any(|&(ex, ey, side)| (ex..ex + side).contains(&x) && (ey..ey + side).contains(&y))
+}
+
+/// One path per color, in order of first appearance
+fn render_pixels(
+    render: &RenderData,
+    eyes: &[(usize, usize, usize)],
+    output: &mut String,
+    on: bool,
+) {
+    let margin = render.margin;
+    let fill = |module: PlacedModule| {
+        // the margin is covered by the background
+        let role = module.role?;
+        if module.dark != on || module.hidden || in_eye(eyes, module.x - margin, module.y - margin)
+        {
+            return None;
+        }
+        Some(match on {
+            true => render.color_of(role),
+            false => &render.background,
+        })
+    };
+    let unit = render.unit as f64;
+    let size = unit * render.module_size;
+    // keep module centered if size != unit
+    let offset = (unit - size) / 2.0;
+
+    let merged = |module: PlacedModule| {
+        render.path_mode != PathMode::Modules
+            && size == unit
+            && module
+                .role
+                .is_some_and(|role| render.shape_of(role) == Shape::Square)
+    };
 
-            // keep module centered if size != unit
-            output.push_str(&format!(
+    let mut paths: Vec<(&str, String)> = Vec::new();
+    for module in render.modules() {
+        let Some(color) = fill(module) else {
+            continue;
+        };
+        let (x, y) = (module.x, module.y);
+        let path = match paths.iter().position(|(c, _)| *c == color) {
+            Some(i) => &mut paths[i].1,
+            None => {
+                paths.push((color, String::new()));
+                &mut paths.last_mut().unwrap().1
+            }
+        };
+        if merged(module) {
+            continue;
+        }
+
+        let left = x as f64 * unit + offset;
+        let top = y as f64 * unit + offset;
+        match render.shape_of(module.role.unwrap()) {
+            Shape::Square if size == unit => path.push_str(&format!(
                 "M{},{}h{}v{}h-{}z",
-                (x as u32 + render.margin as u32) * render.unit as u32,
-                (y as u32 + render.margin as u32) * render.unit as u32,
+                x * render.unit,
+                y * render.unit,
                 render.unit,
                 render.unit,
                 render.unit
-            ));
+            )),
+            Shape::Square => rounded_rect(path, left, top, size, size, [0.0; 4]),
+            Shape::RoundedSquare => rounded_rect(path, left, top, size, size, [size / 4.0; 4]),
+            Shape::Circle => rounded_rect(path, left, top, size, size, [size / 2.0; 4]),
+            Shape::Diamond => {
+                let half = num(size / 2.0);
+                path.push_str(&format!(
+                    "M{},{}l{half},{half}l-{half},{half}l-{half},-{half}z",
+                    num(left + size / 2.0),
+                    num(top)
+                ));
+            }
+            Shape::Liquid => {
+                // only merge with neighbours of the same color
+                let neighbour = |dx: isize, dy: isize| match (
+                    x.checked_add_signed(dx),
+                    y.checked_add_signed(dy),
+                ) {
+                    (Some(x), Some(y)) => fill(render.module_at(x, y)) == Some(color),
+                    _ => false,
+                };
+                let (up, right) = (neighbour(0, -1), neighbour(1, 0));
+                let (down, left_neighbour) = (neighbour(0, 1), neighbour(-1, 0));
+                let corner = |a: bool, b: bool| if a || b { 0.0 } else { size / 2.0 };
+                let radii = [
+                    corner(up, left_neighbour),
+                    corner(up, right),
+                    corner(down, right),
+                    corner(down, left_neighbour),
+                ];
+                rounded_rect(path, left, top, size, size, radii);
+
+                // bridge the gap to the next module
+                if offset > 0.0 && right {
+                    rounded_rect(path, left + size, top, offset * 2.0, size, [0.0; 4]);
+                }
+                if offset > 0.0 && down {
+                    rounded_rect(path, left, top + size, size, offset * 2.0, [0.0; 4]);
+                }
+            }
+        }
+    }
+
+    for (color, mut path) in paths {
+        if render.path_mode != PathMode::Modules {
+            let is_in = |x: usize, y: usize| {
+                let module = render.module_at(x + margin, y + margin);
+                merged(module) && fill(module) == Some(color)
+            };
+            outline(&mut path, render, is_in);
+        }
+        output.push_str(&format!("<path fill=\"{}\" d=\"{path}\"/>", escape(color)));
+    }
+}
+
+/// Traces the outlines of the modules where `is_in` is true. Regions are clockwise and holes counter-clockwise,
+/// so the default nonzero fill rule leaves holes empty.
+fn outline(path: &mut String, render: &RenderData, is_in: impl Fn(usize, usize) -> bool) {
+    // right, down, left, up
+    const STEPS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    let (width, height) = (render.qr_code.matrix.width, render.qr_code.matrix.height);
+    let is_in = |x: isize, y: isize| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && is_in(x as usize, y as usize)
+    };
+
+    // outgoing edges of each corner as bit flags of STEPS, with the module on the right of the edge
+    let corners = width + 1;
+    let mut edges = vec![0u8; corners * (height + 1)];
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            if !is_in(x, y) {
+                continue;
+            }
+            let corner = |x: isize, y: isize| y as usize * corners + x as usize;
+            if !is_in(x, y - 1) {
+                edges[corner(x, y)] |= 1 << 0;
+            }
+            if !is_in(x + 1, y) {
+                edges[corner(x + 1, y)] |= 1 << 1;
+            }
+            if !is_in(x, y + 1) {
+                edges[corner(x + 1, y + 1)] |= 1 << 2;
+            }
+            if !is_in(x - 1, y) {
+                edges[corner(x, y + 1)] |= 1 << 3;
+            }
+        }
+    }
+
+    let scale = render.unit as isize;
+    for start in 0..edges.len() {
+        if edges[start] == 0 {
+            continue;
+        }
+        let (x, y) = ((start % corners) as isize, (start / corners) as isize);
+        path.push_str(&format!(
+            "M{},{}",
+            (x + render.margin as isize) * scale,
+            (y + render.margin as isize) * scale
+        ));
+
+        let mut corner = start;
+        let mut direction = edges[start].trailing_zeros() as usize;
+        let mut length = 0;
+        loop {
+            edges[corner] &= !(1 << direction);
+            let (dx, dy) = STEPS[direction];
+            corner = (corner as isize + dy * corners as isize + dx) as usize;
+            length += 1;
+            if corner == start {
+                break;
+            }
+
+            // turn right first, so regions touching at a corner stay separate loops
+            let next = [1, 0, 3]
+                .map(|turn| (direction + turn) % 4)
+                .into_iter()
+                .find(|&next| edges[corner] & (1 << next) != 0)
+                .unwrap();
+            if next != direction {
+                push_step(path, direction, length * scale);
+                direction = next;
+                length = 0;
+            }
+        }
+        // the last side is closed by z
+        path.push('z');
+    }
+}
+
+fn push_step(path: &mut String, direction: usize, length: isize) {
+    path.push_str(&match direction {
+        0 => format!("h{length}"),
+        1 => format!("v{length}"),
+        2 => format!("h-{length}"),
+        _ => format!("v-{length}"),
+    });
+}
+
+/// Ring and center of each finder pattern, the ring's hole is cut out with evenodd
+fn render_eyes(render: &RenderData, eyes: &[(usize, usize, usize)], output: &mut String) {
+    let unit = render.unit as f64;
+    let radius = |side: f64| match render.finder_shape {
+        FinderShape::Modules | FinderShape::Square => 0.0,
+        FinderShape::Rounded => side / 3.5,
+        FinderShape::Circle => side / 2.0,
+    };
+    let mut rings = String::new();
+    let mut centers = String::new();
+    for &(x, y, side) in eyes {
+        let left = (x + render.margin) as f64 * unit;
+        let top = (y + render.margin) as f64 * unit;
+        for inset in 0..3 {
+            let path = if inset < 2 { &mut rings } else { &mut centers };
+            let inset = inset as f64 * unit;
+            let side = side as f64 * unit - inset * 2.0;
+            rounded_rect(
+                path,
+                left + inset,
+                top + inset,
+                side,
+                side,
+                [radius(side); 4],
+            );
+        }
+    }
+
+    output.push_str(&format!(
+        "<path fill=\"{}\" fill-rule=\"evenodd\" d=\"{rings}\"/>",
+        escape(render.color_of(Role::Finder))
+    ));
+    output.push_str(&format!(
+        "<path fill=\"{}\" d=\"{centers}\"/>",
+        escape(render.color_of(Role::FinderCenter))
+    ));
+}
+
+/// Clockwise from the top left corner, each corner has its own radius
+fn rounded_rect(
+    output: &mut String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    [top_left, top_right, bottom_right, bottom_left]: [f64; 4],
+) {
+    output.push_str(&format!("M{},{}", num(x + top_left), num(y)));
+    line(output, 'h', width - top_left - top_right);
+    arc(output, top_right, 1.0, 1.0);
+    line(output, 'v', height - top_right - bottom_right);
+    arc(output, bottom_right, -1.0, 1.0);
+    line(output, 'h', -(width - bottom_right - bottom_left));
+    arc(output, bottom_left, -1.0, -1.0);
+    line(output, 'v', -(height - bottom_left - top_left));
+    arc(output, top_left, 1.0, -1.0);
+    output.push('z');
+}
+
+fn line(output: &mut String, command: char, length: f64) {
+    let length = num(length);
+    if length != 0.0 {
+        output.push_str(&format!("{command}{length}"));
+    }
+}
+
+/// Quarter circle, `dx` and `dy` are the signs of the end point
+fn arc(output: &mut String, radius: f64, dx: f64, dy: f64) {
+    let radius = num(radius);
+    if radius > 0.0 {
+        output.push_str(&format!(
+            "a{radius},{radius} 0 0 1 {},{}",
+            radius * dx,
+            radius * dy
+        ));
+    }
+}
+
+/// For attribute values
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Rounds to 2 decimals to keep the output short, whole numbers print without a decimal point
+fn num(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate, generate_micro, generate_rmqr,
+        qr_code::{Version, ECL},
+        render::{Logo, PhysicalSize},
+        QrOptions,
+    };
+
+    #[test]
+    fn render_svg_squares() {
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let svg = render_svg(&RenderData::new(&qr_code));
+        assert!(svg.starts_with(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 15 15"><rect width="15" height="15" fill="#fff"/><path fill="#000" d="M2,2h1v1h-1zM3,2h1v1h-1z"##
+        ));
+
+        let svg = render_svg(&RenderData::new(&qr_code).unit(10).module_size(0.5));
+        assert!(svg.contains(r#"d="M22.5,22.5h5v5h-5v-5zM32.5,22.5h5v5h-5v-5z"#));
+        let svg = render_svg(&RenderData::new(&qr_code).shape(Shape::Diamond));
+        assert!(svg.contains(r#"d="M2.5,2l0.5,0.5l-0.5,0.5l-0.5,-0.5z"#));
+    }
+
+    #[test]
+    fn render_svg_role_colors() {
+        let qr_code = generate("1", &QrOptions::new().min_version(Version(7))).unwrap();
+        let render = RenderData::new(&qr_code)
+            .role_color(Role::Finder, "#f00".into())
+            .role_color(Role::FinderCenter, "#0f0".into())
+            .role_color(Role::Version, "#00f".into());
+        let svg = render_svg(&render);
+        assert_eq!(svg.matches("<path").count(), 4);
+        assert!(svg.contains(r##"<path fill="#f00" d="M2,2h1v1h-1z"##));
+        assert!(svg.contains(r##"<path fill="#0f0" d="M4,4h1v1h-1z"##));
+        assert!(svg.contains(r##"<path fill="#00f" d=""##));
+
+        let svg = render_svg(&render.finder_shape(FinderShape::Square));
+        assert!(svg.contains(
+            r##"<path fill="#f00" fill-rule="evenodd" d="M2,2h7v7h-7v-7zM3,3h5v5h-5v-5z"##
+        ));
+        assert!(svg.contains(r##"<path fill="#0f0" d="M4,4h3v3h-3v-3z"##));
+
+        let module = |x, y| Role::of(qr_code.matrix.get(x, y));
+        assert_eq!(module(0, 0), Role::Finder);
+        assert_eq!(module(3, 3), Role::FinderCenter);
+        assert_eq!(module(6, 6), Role::Finder);
+        assert_eq!(module(6, 10), Role::Timing);
+        assert_eq!(module(22, 22), Role::Alignment);
+        assert_eq!(module(8, 0), Role::Format);
+        assert_eq!(module(8, qr_code.matrix.width - 8), Role::Format);
+        assert_eq!(module(0, qr_code.matrix.width - 11), Role::Version);
+        assert_eq!(module(12, 12), Role::Data);
+    }
+
+    #[test]
+    fn render_svg_escapes_colors() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code)
+            .background(r#"#fff" onload="x"#.into())
+            .role_color(Role::Timing, "<&".into())
+            .role_color(Role::Finder, "\"".into());
+        for svg in [
+            render_svg(&render),
+            render_svg(&render.clone().finder_shape(FinderShape::Circle)),
+        ] {
+            assert!(svg.contains(r##"fill="#fff&quot; onload=&quot;x""##));
+            assert!(svg.contains(r#"<path fill="&lt;&amp;" d="#));
+            assert!(svg.contains(r#"<path fill="&quot;" "#));
+            assert!(!svg.contains("onload=\""));
+        }
+    }
+
+    #[test]
+    fn render_svg_merged() {
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code).unit(10);
+        let svg = render_svg(&render.clone().path_mode(PathMode::MergedPixels));
+        // finder ring, its hole and the finder center
+        assert!(svg.contains(r##"<path fill="#000" d="M20,20h70v70h-70zM100,20"##));
+        assert!(svg.contains("zM30,30v50h50v-50zM40,40h30v30h-30z"));
+        let svg = render_svg(&render.clone().path_mode(PathMode::Merged));
+        assert!(svg.starts_with(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 15 15" width="150" height="150"><rect width="15" height="15" fill="#fff"/><path fill="#000" d="M2,2h7v7h-7zM10,2"##
+        ));
+
+        let qr_code = generate("1", &QrOptions::new().min_version(Version(40))).unwrap();
+        let render = RenderData::new(&qr_code);
+        let modules = render_svg(&render);
+        let merged = render_svg(&render.path_mode(PathMode::Merged));
+        assert!(merged.len() * 2 < modules.len());
+    }
+
+    #[test]
+    fn render_svg_physical_size() {
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        // 10 px per module
+        let render = RenderData::new(&qr_code)
+            .physical_size(PhysicalSize::ModuleSize(1.0), 254.0)
+            .unwrap();
+        assert!(render_svg(&render).starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 150 150" width="15mm" height="15mm">"#
+        ));
+        assert!(render_svg(&render.path_mode(PathMode::Merged)).starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 15 15" width="15mm" height="15mm">"#
+        ));
+    }
+
+    #[test]
+    fn render_svg_logo() {
+        let options = QrOptions::new().min_version(Version(7)).min_ecl(ECL::High);
+        let qr_code = generate("https://github.com/subygan/qrust", &options).unwrap();
+        let logo = Logo {
+            href: r#"data:image/svg+xml,<svg xmlns="http://www.w3.org/2000/svg"/>"#.into(),
+            width: 9,
+            height: 7,
+        };
+        let render = RenderData::new(&qr_code).unit(2).logo(logo).unwrap();
+        let svg = render_svg(&render);
+        assert!(svg.contains(
+            r#"<image href="data:image/svg+xml,&lt;svg xmlns=&quot;http://www.w3.org/2000/svg&quot;/>" x="40" y="42" width="18" height="14"/>"#
+        ));
+        // center of the alignment pattern under the logo
+        assert!(svg.contains("M48,48h2v2h-2z"));
+        assert!(!render_svg(&RenderData::new(&qr_code).unit(2)).contains("<image"));
+    }
+
+    #[test]
+    fn render_svg_fills() {
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let fill = Fill::LinearGradient {
+            from: (0.0, 0.0),
+            to: (1.0, 0.5),
+            stops: vec![(0.0, "#f00".into()), (1.0, "#00f".into())],
+        };
+        let image = Fill::Image {
+            href: "paper.png".into(),
+            width: 3,
+            height: 2,
+        };
+        let render = RenderData::new(&qr_code)
+            .unit(2)
+            .foreground_fill(fill)
+            .background_fill(image)
+            .role_color(Role::Timing, "#0f0".into());
+        let svg = render_svg(&render);
+        assert!(svg.contains(
+            r##"<defs><linearGradient id="qrust-foreground" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="30" y2="15"><stop offset="0" stop-color="#f00"/><stop offset="1" stop-color="#00f"/></linearGradient><pattern id="qrust-background" patternUnits="userSpaceOnUse" width="6" height="4"><image href="paper.png" width="6" height="4"/></pattern></defs>"##
+        ));
+        assert!(svg.contains(r##"<rect width="30" height="30" fill="url(#qrust-background)"/>"##));
+        assert!(svg.contains(r##"<path fill="url(#qrust-foreground)" d="M4,4"##));
+        assert!(svg.contains(r##"<path fill="#0f0" d="##));
+
+        let fill = Fill::RadialGradient {
+            stops: vec![(0.5, "#000".into())],
+        };
+        let render = RenderData::new(&qr_code)
+            .unit(2)
+            .foreground_fill(fill)
+            .path_mode(PathMode::Merged);
+        assert!(render_svg(&render).contains(r##"<radialGradient id="qrust-foreground" gradientUnits="userSpaceOnUse" cx="7.5" cy="7.5" r="7.5"><stop offset="0.5" stop-color="#000"/></radialGradient>"##));
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn render_svg_gradient_rasterizes() {
+        use super::super::raster::render_rgba;
+
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        let fill = Fill::LinearGradient {
+            from: (0.0, 0.0),
+            to: (1.0, 0.0),
+            stops: vec![(0.0, "#f00".into()), (1.0, "#00f".into())],
+        };
+        let render = RenderData::new(&qr_code).unit(4).foreground_fill(fill);
+        let rgba = render_rgba(&render).unwrap();
+        let pixel = |x: usize, y: usize| &rgba[(y * render.width() + x) * 4..][..3];
+        // outer rings of the top left and top right finders
+        let (left, right) = (pixel(9, 9), pixel(render.width() - 10, 9));
+        assert!(left[0] > 200 && left[2] < 55);
+        assert!(right[0] < 55 && right[2] > 200);
+    }
+
+    #[test]
+    fn find_eyes_works() {
+        let eyes = |qr_code| find_eyes(&RenderData::new(&qr_code));
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        assert_eq!(eyes(qr_code), [(0, 0, 7), (14, 0, 7), (0, 14, 7)]);
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        assert_eq!(eyes(qr_code), [(0, 0, 7)]);
+        let qr_code = generate_rmqr("1", &QrOptions::new()).unwrap();
+        assert_eq!(eyes(qr_code), [(0, 0, 7), (38, 2, 5)]);
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn shapes_keep_module_centers() {
+        use super::super::raster::render_rgba;
+
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let rmqr = generate_rmqr("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let shapes = [
+            Shape::Square,
+            Shape::Circle,
+            Shape::RoundedSquare,
+            Shape::Diamond,
+            Shape::Liquid,
+        ];
+        let finder_shapes = [
+            FinderShape::Modules,
+            FinderShape::Square,
+            FinderShape::Rounded,
+            FinderShape::Circle,
+        ];
+        for (qr_code, shape, finder_shape, module_size) in [&qr_code, &rmqr]
+            .into_iter()
+            .flat_map(|qr_code| shapes.map(|shape| (qr_code, shape)))
+            .flat_map(|(qr_code, shape)| finder_shapes.map(|f| (qr_code, shape, f)))
+            .flat_map(|(qr_code, shape, f)| [0.7, 1.0].map(|size| (qr_code, shape, f, size)))
+        {
+            let path_mode = match module_size == 1.0 {
+                true => PathMode::Merged,
+                false => PathMode::Modules,
+            };
+            let render = RenderData::new(qr_code)
+                .unit(10)
+                .shape(shape)
+                .finder_shape(finder_shape)
+                .module_size(module_size)
+                .path_mode(path_mode);
+            let eyes = match finder_shape {
+                FinderShape::Modules => Vec::new(),
+                _ => find_eyes(&render),
+            };
+            let rgba = render_rgba(&render).unwrap();
+            let pixel = |x: usize, y: usize| {
+                let i = ((y + 2) * 10 + 5) * render.width() + (x + 2) * 10 + 5;
+                rgba[i * 4]
+            };
+
+            for y in 0..qr_code.matrix.height {
+                for x in 0..qr_code.matrix.width {
+                    if in_eye(&eyes, x, y) {
+                        continue;
+                    }
+                    let expected = if qr_code.matrix.get(x, y).has(Module::ON) {
+                        0
+                    } else {
+                        255
+                    };
+                    assert_eq!(pixel(x, y), expected, "{shape:?} {finder_shape:?} {x},{y}");
+                }
+            }
+            for (x, y, side) in eyes {
+                // center, hole and the middle of the ring's top edge
+                assert_eq!(pixel(x + side / 2, y + side / 2), 0);
+                assert_eq!(pixel(x + 1, y + side / 2), 255);
+                assert_eq!(pixel(x + side / 2, y), 0);
+            }
         }
     }
-    output.push_str("\"/>");
 }
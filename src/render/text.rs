@@ -1,50 +1,495 @@
-use crate::matrix::Module;
+use crate::{qr_code::Symbol, RenderError};
 
-use super::RenderData;
+use super::{rgb, RenderData, Renderer};
 
+/// xterm's 16 colors
+const ANSI_16: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+/// Channel levels of the 6x6x6 cube in the 256 color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorDepth {
+    /// Nearest of the 16 standard colors
+    Ansi16,
+    /// Nearest of the 256 color palette
+    Ansi256,
+    /// 24-bit color
+    TrueColor,
+}
+
+/// `Renderer`s of `render_utf8`, `render_ascii`, `render_braille` and `render_quadrants`
+#[derive(Clone, Copy, Debug)]
+pub struct Utf8;
+#[derive(Clone, Copy, Debug)]
+pub struct Ascii;
+#[derive(Clone, Copy, Debug)]
+pub struct Braille;
+#[derive(Clone, Copy, Debug)]
+pub struct Quadrants;
+
+/// `Renderer` of `render_ansi`
+#[derive(Clone, Copy, Debug)]
+pub struct Ansi {
+    pub depth: ColorDepth,
+    pub invert: bool,
+}
+
+impl Renderer for Utf8 {
+    type Output = String;
+
+    fn render(&self, render: &RenderData) -> String {
+        render_utf8(render)
+    }
+}
+
+impl Renderer for Ascii {
+    type Output = String;
+
+    fn render(&self, render: &RenderData) -> String {
+        render_ascii(render)
+    }
+}
+
+impl Renderer for Braille {
+    type Output = String;
+
+    fn render(&self, render: &RenderData) -> String {
+        render_braille(render)
+    }
+}
+
+impl Renderer for Quadrants {
+    type Output = String;
+
+    fn render(&self, render: &RenderData) -> String {
+        render_quadrants(render)
+    }
+}
+
+impl Renderer for Ansi {
+    type Output = Result<String, RenderError>;
+
+    fn render(&self, render: &RenderData) -> Self::Output {
+        render_ansi(render, self.depth, self.invert)
+    }
+}
+
+/// Half blocks, 1x2 modules per character. `unit` is ignored.
 pub fn render_utf8(render: &RenderData) -> String {
-    // row length +1 for \n and take ceil of rows / 2 if odd
-    let mut result = String::with_capacity((render.width() + 1) * (render.width() + 1) / 2);
+    render_cells(render, 1, 2, |cell| match (cell(0, 0), cell(0, 1)) {
+        (true, true) => '█',
+        (true, false) => '▀',
+        (false, true) => '▄',
+        (false, false) => ' ',
+    })
+}
 
-    let start = render.margin;
-    let end = render.qr_code.matrix.width + start;
+/// Two characters per module, `##` for dark and two spaces for light. `unit` is ignored.
+pub fn render_ascii(render: &RenderData) -> String {
+    let (width, height) = render.grid_size();
+    let mut result = String::with_capacity((width * 2 + 1) * height);
+    for y in 0..height {
+        for x in 0..width {
+            result.push_str(if is_dark(render, x, y) { "##" } else { "  " });
+        }
+        result.push('\n');
+    }
+    result
+}
 
-    for y in (0..render.width()).step_by(2) {
-        for x in 0..render.width() {
-            if x < start || x >= end {
-                result.push(' ');
-                continue;
+/// 2x4 modules per Braille character, raised dots are dark. `unit` is ignored.
+pub fn render_braille(render: &RenderData) -> String {
+    // dot bit of each (x, y) in the cell
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    render_cells(render, 2, 4, |cell| {
+        let mut dots = 0;
+        for (x, column) in DOTS.iter().enumerate() {
+            for (y, dot) in column.iter().enumerate() {
+                if cell(x, y) {
+                    dots |= dot;
+                }
             }
+        }
+        char::from_u32(0x2800 + dots).unwrap()
+    })
+}
 
-            let top = if y >= start && y < end {
-                render
-                    .qr_code
-                    .matrix
-                    .get(x - start, y - start)
-                    .has(Module::ON)
-            } else {
-                false
-            };
-
-            let bot = if y + 1 >= start && y + 1 < end {
-                render
-                    .qr_code
-                    .matrix
-                    .get(x - start, y - start + 1)
-                    .has(Module::ON)
-            } else {
-                false
-            };
+/// 2x2 modules per quadrant block character. `unit` is ignored.
+pub fn render_quadrants(render: &RenderData) -> String {
+    // indexed by top left 1, top right 2, bottom left 4, bottom right 8
+    const QUADRANTS: [char; 16] = [
+        ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+    ];
+    render_cells(render, 2, 2, |cell| {
+        let i = cell(0, 0) as usize
+            | (cell(1, 0) as usize) << 1
+            | (cell(0, 1) as usize) << 2
+            | (cell(1, 1) as usize) << 3;
+        QUADRANTS[i]
+    })
+}
 
-            let c = match (top, bot) {
-                (true, true) => '█',
-                (true, false) => '▀',
-                (false, true) => '▄',
-                (false, false) => ' ',
-            };
-            result.push(c);
+/// One character per `cell_width` x `cell_height` modules, `to_char` gets whether each module of the cell is dark
+fn render_cells(
+    render: &RenderData,
+    cell_width: usize,
+    cell_height: usize,
+    to_char: impl Fn(&dyn Fn(usize, usize) -> bool) -> char,
+) -> String {
+    let (width, height) = render.grid_size();
+    let mut result =
+        String::with_capacity((width.div_ceil(cell_width) + 1) * height.div_ceil(cell_height) * 3);
+    for y in (0..height).step_by(cell_height) {
+        for x in (0..width).step_by(cell_width) {
+            let cell = |dx: usize, dy: usize| is_dark(render, x + dx, y + dy);
+            result.push(to_char(&cell));
         }
         result.push('\n');
     }
     result
 }
+
+/// `x` and `y` include the margin, anything outside the symbol is light
+fn is_dark(render: &RenderData, x: usize, y: usize) -> bool {
+    render.module_at(x, y).dark
+}
+
+/// Half blocks with ANSI foreground and background colors, so the code looks the same on light and dark terminals.
+/// Colors are `foreground`, `background` and role colors, `#rgb` or `#rrggbb` only, others are a `RenderError::InvalidColor`.
+/// `invert` swaps dark and light modules, a light on dark code that blends into dark themes, which most phone scanners
+/// still read. `unit` is ignored and `margin` is at least the quiet zone, 4 modules or 2 for Micro QR and rMQR.
+pub fn render_ansi(
+    render: &RenderData,
+    depth: ColorDepth,
+    invert: bool,
+) -> Result<String, RenderError> {
+    let quiet_zone = match render.qr_code.symbol {
+        Symbol::Normal => 4,
+        Symbol::Micro | Symbol::Rmqr => 2,
+    };
+    let render = render.resolved();
+    let render = &render.clone().margin(render.margin.max(quiet_zone));
+    let (width, height) = render.grid_size();
+
+    let (foreground, background, roles) = render.parse_colors(rgb)?;
+    let color = |x: usize, y: usize| {
+        let module = render.module_at(x, y);
+        let dark = match module.role {
+            Some(role) if module.dark => Some(roles[role as usize]),
+            _ => None,
+        };
+        match (dark, invert) {
+            (Some(_), true) => background,
+            (Some(dark), false) => dark,
+            (None, true) => foreground,
+            (None, false) => background,
+        }
+    };
+
+    let mut result = String::with_capacity(width * height.div_ceil(2) * 4);
+    for y in (0..height).step_by(2) {
+        let mut last = None;
+        for x in 0..width {
+            // the bottom half of an odd last row is the terminal's own background
+            let top = color(x, y);
+            let bottom = (y + 1 < height).then(|| color(x, y + 1));
+            if last != Some((top, bottom)) {
+                let escape = match bottom {
+                    Some(bottom) => format!(
+                        "{};{}",
+                        ansi_color(depth, top, false),
+                        ansi_color(depth, bottom, true)
+                    ),
+                    None => format!("0;{}", ansi_color(depth, top, false)),
+                };
+                result.push_str(&format!("\x1b[{escape}m"));
+                last = Some((top, bottom));
+            }
+            result.push('▀');
+        }
+        result.push_str("\x1b[0m\n");
+    }
+    Ok(result)
+}
+
+/// SGR parameters of the nearest color in `depth`
+fn ansi_color(depth: ColorDepth, [r, g, b]: [u8; 3], background: bool) -> String {
+    let distance = |[r2, g2, b2]: [u8; 3]| {
+        [r.abs_diff(r2), g.abs_diff(g2), b.abs_diff(b2)]
+            .map(|d| d as u32 * d as u32)
+            .iter()
+            .sum::<u32>()
+    };
+    match depth {
+        ColorDepth::Ansi16 => {
+            let i = (0..16).min_by_key(|&i| distance(ANSI_16[i])).unwrap();
+            let base = match (background, i < 8) {
+                (false, true) => 30,
+                (false, false) => 90 - 8,
+                (true, true) => 40,
+                (true, false) => 100 - 8,
+            };
+            (base + i).to_string()
+        }
+        ColorDepth::Ansi256 => {
+            let level = |c: u8| (0..6).min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c)).unwrap();
+            let cube = [level(r), level(g), level(b)];
+            let gray = ((r as usize + g as usize + b as usize) / 3).saturating_sub(3) / 10;
+            let gray = gray.min(23);
+            let index = match distance(cube.map(|i| CUBE_LEVELS[i]))
+                <= distance([8 + gray as u8 * 10; 3])
+            {
+                true => 16 + 36 * cube[0] + 6 * cube[1] + cube[2],
+                false => 232 + gray,
+            };
+            format!("{};5;{index}", if background { 48 } else { 38 })
+        }
+        ColorDepth::TrueColor => {
+            format!("{};2;{r};{g};{b}", if background { 48 } else { 38 })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decode::decode_bools, generate, generate_micro, qr_code::QrCode, render::Role, QrOptions,
+    };
+
+    /// Reads the module colors back from the escapes
+    fn parse(ansi: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        for line in ansi.lines() {
+            let (mut top_row, mut bottom_row) = (Vec::new(), Vec::new());
+            for part in line.split("\x1b[").filter(|part| !part.is_empty()) {
+                let (escape, blocks) = part.split_once('m').unwrap();
+                let mut params: Vec<&str> = escape.split(';').collect();
+                if params == ["0"] {
+                    continue;
+                }
+                // odd last row, only a foreground
+                if params[0] == "0" {
+                    params.remove(0);
+                }
+                let len = match params[..] {
+                    [_, "5", ..] => 3,
+                    [_, "2", ..] => 5,
+                    _ => 1,
+                };
+                let (foreground, background) = params.split_at(len.min(params.len()));
+                let top = foreground.join(";");
+                // as a foreground color, to compare with the top row
+                let bottom = match background {
+                    [] => String::new(),
+                    [code] => (code.parse::<u8>().unwrap() - 10).to_string(),
+                    [_, rest @ ..] => format!("38;{}", rest.join(";")),
+                };
+                for _ in blocks.chars() {
+                    top_row.push(top.clone());
+                    bottom_row.push(bottom.clone());
+                }
+            }
+            rows.push(top_row);
+            if bottom_row.iter().all(|color| !color.is_empty()) {
+                rows.push(bottom_row);
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn ansi_color_works() {
+        assert_eq!(ansi_color(ColorDepth::Ansi16, [0, 0, 0], false), "30");
+        assert_eq!(ansi_color(ColorDepth::Ansi16, [255, 255, 255], true), "107");
+        assert_eq!(ansi_color(ColorDepth::Ansi16, [200, 10, 10], false), "31");
+        assert_eq!(
+            ansi_color(ColorDepth::Ansi256, [255, 255, 255], false),
+            "38;5;231"
+        );
+        assert_eq!(
+            ansi_color(ColorDepth::Ansi256, [128, 128, 128], true),
+            "48;5;244"
+        );
+        assert_eq!(
+            ansi_color(ColorDepth::Ansi256, [255, 0, 0], false),
+            "38;5;196"
+        );
+        assert_eq!(
+            ansi_color(ColorDepth::TrueColor, [1, 2, 3], true),
+            "48;2;1;2;3"
+        );
+    }
+
+    #[test]
+    fn render_ansi_decodes() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        for depth in [
+            ColorDepth::Ansi16,
+            ColorDepth::Ansi256,
+            ColorDepth::TrueColor,
+        ] {
+            for invert in [false, true] {
+                let render = RenderData::new(&qr_code).foreground("#123".into());
+                let ansi = render_ansi(&render, depth, invert).unwrap();
+                let rows = parse(&ansi);
+
+                // quiet zone
+                let width = qr_code.matrix.width + 8;
+                assert_eq!(rows.len(), width);
+                assert!(rows.iter().all(|row| row.len() == width));
+                let light = rows[0][0].clone();
+                assert!(rows[..4].iter().flatten().all(|color| *color == light));
+
+                let grid: Vec<bool> = rows[4..width - 4]
+                    .iter()
+                    .flat_map(|row| &row[4..width - 4])
+                    .map(|color| *color != light)
+                    .collect();
+                let decoded = decode_bools(&grid, qr_code.matrix.width).unwrap();
+                assert_eq!(decoded.text, "https://github.com/subygan/qrust");
+            }
+        }
+    }
+
+    /// Decodes a grid of modules, including a margin of 2
+    fn decode_grid(rows: Vec<Vec<bool>>, qr_code: &QrCode) -> String {
+        let width = qr_code.matrix.width;
+        assert!(rows.len() >= width + 4);
+        let grid: Vec<bool> = rows[2..width + 2]
+            .iter()
+            .flat_map(|row| &row[2..width + 2])
+            .copied()
+            .collect();
+        decode_bools(&grid, width).unwrap().text
+    }
+
+    #[test]
+    fn render_utf8_decodes() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        // odd margins start with a half block
+        let render = RenderData::new(&qr_code).margin(3);
+        let mut rows: Vec<Vec<bool>> = Vec::new();
+        for line in Utf8.render(&render).lines() {
+            let (top, bottom) = line
+                .chars()
+                .map(|c| (matches!(c, '█' | '▀'), matches!(c, '█' | '▄')))
+                .unzip();
+            rows.extend([top, bottom]);
+        }
+        // down to the margin of 2 decode_grid expects
+        let rows = rows[1..].iter().map(|row| row[1..].to_vec()).collect();
+        assert_eq!(
+            decode_grid(rows, &qr_code),
+            "https://github.com/subygan/qrust"
+        );
+    }
+
+    #[test]
+    fn text_renderers_decode() {
+        let qr_code = generate("https://github.com/subygan/qrust", &QrOptions::new()).unwrap();
+        let render = RenderData::new(&qr_code);
+        let width = qr_code.matrix.width + 4;
+
+        let ascii = render_ascii(&render);
+        let rows = ascii
+            .lines()
+            .map(|line| {
+                assert_eq!(line.len(), width * 2);
+                line.as_bytes().chunks(2).map(|c| c == b"##").collect()
+            })
+            .collect();
+        assert_eq!(
+            decode_grid(rows, &qr_code),
+            "https://github.com/subygan/qrust"
+        );
+
+        let braille = render_braille(&render);
+        let mut rows = vec![vec![false; width.div_ceil(2) * 2]; width.div_ceil(4) * 4];
+        for (cy, line) in braille.lines().enumerate() {
+            for (cx, c) in line.chars().enumerate() {
+                let dots = c as u32 - 0x2800;
+                for (bit, (x, y)) in [
+                    (0, 0),
+                    (0, 1),
+                    (0, 2),
+                    (1, 0),
+                    (1, 1),
+                    (1, 2),
+                    (0, 3),
+                    (1, 3),
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    rows[cy * 4 + y][cx * 2 + x] = dots >> bit & 1 == 1;
+                }
+            }
+        }
+        assert!(rows[width..].iter().flatten().all(|&dark| !dark));
+        assert_eq!(
+            decode_grid(rows, &qr_code),
+            "https://github.com/subygan/qrust"
+        );
+
+        let quadrants = render_quadrants(&render);
+        let mut rows = vec![vec![false; width.div_ceil(2) * 2]; width.div_ceil(2) * 2];
+        for (cy, line) in quadrants.lines().enumerate() {
+            for (cx, c) in line.chars().enumerate() {
+                let i = " ▘▝▀▖▌▞▛▗▚▐▜▄▙▟█".chars().position(|q| q == c).unwrap();
+                for (bit, (x, y)) in [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().enumerate() {
+                    rows[cy * 2 + y][cx * 2 + x] = i >> bit & 1 == 1;
+                }
+            }
+        }
+        assert_eq!(
+            decode_grid(rows, &qr_code),
+            "https://github.com/subygan/qrust"
+        );
+    }
+
+    #[test]
+    fn render_ansi_quiet_zone() {
+        let qr_code = generate_micro("1", &QrOptions::new()).unwrap();
+        let ansi = render_ansi(&RenderData::new(&qr_code), ColorDepth::TrueColor, false).unwrap();
+        // 11 modules + 2 * 2, with an odd last row
+        assert_eq!(ansi.lines().count(), 8);
+        assert!(ansi
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("\x1b[0;38;2;255;255;255m"));
+        assert!(ansi.ends_with("▀\x1b[0m\n"));
+    }
+
+    #[test]
+    fn render_ansi_rejects_unparseable_colors() {
+        let qr_code = generate("1", &QrOptions::new()).unwrap();
+        for render in [
+            RenderData::new(&qr_code).background("white".into()),
+            RenderData::new(&qr_code).role_color(Role::Timing, "#12345".into()),
+        ] {
+            assert_eq!(
+                render_ansi(&render, ColorDepth::TrueColor, false),
+                Err(RenderError::InvalidColor)
+            );
+        }
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "raster")]
+use crate::RasterError;
 use crate::{
     bit_info::BitInfo,
     qr_code::{Mask, Mode, QrCode, Version, ECL},
@@ -18,6 +20,27 @@ pub fn generate(input: &str, qr_options: &QrOptions) -> Result<JsValue, QrError>
     Ok(qr_code_to_obj(qr_code))
 }
 
+#[wasm_bindgen(js_name = generateStructured)]
+pub fn generate_structured(input: &str, qr_options: &QrOptions) -> Result<js_sys::Array, QrError> {
+    console_error_panic_hook::set_once();
+    let qr_codes = crate::generate_structured(input, qr_options)?;
+    Ok(qr_codes.into_iter().map(qr_code_to_obj).collect())
+}
+
+#[wasm_bindgen(js_name = generateMicro)]
+pub fn generate_micro(input: &str, qr_options: &QrOptions) -> Result<JsValue, QrError> {
+    console_error_panic_hook::set_once();
+    let qr_code = crate::generate_micro(input, qr_options)?;
+    Ok(qr_code_to_obj(qr_code))
+}
+
+#[wasm_bindgen(js_name = generateRmqr)]
+pub fn generate_rmqr(input: &str, qr_options: &QrOptions) -> Result<JsValue, QrError> {
+    console_error_panic_hook::set_once();
+    let qr_code = crate::generate_rmqr(input, qr_options)?;
+    Ok(qr_code_to_obj(qr_code))
+}
+
 #[wasm_bindgen(js_name = generateQart)]
 pub fn generate_qart(
     input: &str,
@@ -33,6 +56,42 @@ pub fn generate_qart(
     Ok(qr_code_to_obj(qr_code))
 }
 
+/// N1-N4 penalties of every mask for the symbol `generate` makes, null for Micro QR and rMQR
+#[wasm_bindgen(js_name = maskPenalties)]
+pub fn mask_penalties(input: &str, qr_options: &QrOptions) -> Result<JsValue, QrError> {
+    console_error_panic_hook::set_once();
+    let qr_code = crate::generate(input, qr_options)?;
+    let Some(mask_penalties) = qr_code.mask_penalties() else {
+        return Ok(JsValue::NULL);
+    };
+
+    let penalties = js_sys::Array::new();
+    for penalty in mask_penalties.penalties {
+        let obj = js_sys::Object::new();
+        // If these error, it's not recoverable
+        let _ = js_sys::Reflect::set(&obj, &"n1".into(), &JsValue::from(penalty.n1));
+        let _ = js_sys::Reflect::set(&obj, &"n2".into(), &JsValue::from(penalty.n2));
+        let _ = js_sys::Reflect::set(&obj, &"n3".into(), &JsValue::from(penalty.n3));
+        let _ = js_sys::Reflect::set(&obj, &"n4".into(), &JsValue::from(penalty.n4));
+        let _ = js_sys::Reflect::set(&obj, &"total".into(), &JsValue::from(penalty.total()));
+        penalties.push(&obj);
+    }
+
+    let obj = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&obj, &"penalties".into(), &penalties);
+    let _ = js_sys::Reflect::set(&obj, &"best".into(), &JsValue::from(mask_penalties.best));
+    let _ = js_sys::Reflect::set(&obj, &"mask".into(), &JsValue::from(qr_code.mask));
+    Ok(obj.into())
+}
+
+/// PNG bytes of any SVG, rasterised with the same resvg renderer as native builds
+#[cfg(feature = "raster")]
+#[wasm_bindgen(js_name = rasterizeSvg)]
+pub fn rasterize_svg(svg: &str, width: u32, height: u32) -> Result<Vec<u8>, RasterError> {
+    console_error_panic_hook::set_once();
+    crate::render::raster::svg_to_png(svg, width, height)
+}
+
 fn qr_code_to_obj(qr_code: QrCode) -> JsValue {
     let u = js_sys::Uint8Array::new_with_length(qr_code.matrix.value.len() as u32);
     u.copy_from(unsafe { std::mem::transmute(qr_code.matrix.value.as_slice()) });
@@ -40,10 +99,17 @@ fn qr_code_to_obj(qr_code: QrCode) -> JsValue {
     let obj = js_sys::Object::new();
     // If these error, it's not recoverable
     let _ = js_sys::Reflect::set(&obj, &"matrix".into(), &u);
+    let _ = js_sys::Reflect::set(&obj, &"width".into(), &JsValue::from(qr_code.matrix.width));
+    let _ = js_sys::Reflect::set(
+        &obj,
+        &"height".into(),
+        &JsValue::from(qr_code.matrix.height),
+    );
     let _ = js_sys::Reflect::set(&obj, &"mode".into(), &JsValue::from(qr_code.mode));
     let _ = js_sys::Reflect::set(&obj, &"version".into(), &JsValue::from(qr_code.version.0));
     let _ = js_sys::Reflect::set(&obj, &"ecl".into(), &JsValue::from(qr_code.ecl));
     let _ = js_sys::Reflect::set(&obj, &"mask".into(), &JsValue::from(qr_code.mask));
+    let _ = js_sys::Reflect::set(&obj, &"symbol".into(), &JsValue::from(qr_code.symbol));
 
     obj.into()
 }